@@ -0,0 +1,20 @@
+use embassy_rp::adc::{Adc, Async, Channel};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
+
+use crate::sensor::SensorError;
+
+/// The RP2040 has a single ADC, so drivers that use it share it through a mutex
+pub type SharedAdc<'d> = Mutex<ThreadModeRawMutex, Adc<'d, Async>>;
+
+pub const ADC_MAX: u16 = 4095;  // Full scale reading of the 12 bit ADC
+
+/// Read a channel `samples` times and return the average
+pub async fn read_average(adc: &SharedAdc<'_>, channel: &mut Channel<'_>, samples: u8) -> Result<u16, SensorError> {
+    let mut adc = adc.lock().await;
+    let mut total: u32 = 0;
+    for _ in 0..samples.max(1) {
+        total += adc.read(channel).await.map_err(|_| SensorError::BusError)? as u32;
+    }
+    Ok((total / samples.max(1) as u32) as u16)
+}
@@ -1,14 +1,28 @@
 //use defmt::Str;
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+use display_interface_i2c::I2CInterface;
+use display_interface_spi::SPIInterface;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+use embedded_hal_async::spi::SpiDevice;
+#[cfg(feature = "rp2040")]
 use embedded_hal_bus::spi::ExclusiveDevice;
+#[cfg(feature = "rp2040")]
 use embassy_rp::peripherals::{DMA_CH0, SPI1};
+#[cfg(feature = "rp2040")]
 use embassy_rp::spi::{Async, Config, Spi};
+#[cfg(feature = "rp2040")]
 use embassy_rp::gpio::Output;
 use embassy_time::{Delay, Duration, Timer};
 use embedded_graphics::prelude::*;
-use embedded_graphics::image::{Image, ImageRaw};
 use embedded_graphics::pixelcolor::BinaryColor;
+use heapless::String;
+use core::fmt::Write;
 
-use crate::sh1107::SH1107;
+use crate::history::History;
+use crate::oled::{Oled, Rotation};
+use crate::panel::Panel;
+use crate::temperature::TemperatureUnit;
 
 // 32 x 32 logo
 const RUST_LOGO: [u8; 128] = [
@@ -25,6 +39,76 @@ const RUST_LOGO: [u8; 128] = [
   0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
 ];
 
+// 16 x 16 home screen icons
+const HEATING_ICON: [u8; 32] = [
+    0x01, 0x80, 0x01, 0x80, 0x03, 0x80, 0x03, 0xc0, 0x07, 0xc0, 0x07, 0xe0, 0x0e, 0xe0, 0x0c, 0xf0,
+    0x1c, 0x70, 0x18, 0x38, 0x39, 0x38, 0x33, 0x98, 0x33, 0xd8, 0x3b, 0xd8, 0x1f, 0xf8, 0x0f, 0xf0,
+];
+const COOLING_ICON: [u8; 32] = [
+    0x01, 0x80, 0x09, 0x90, 0x05, 0xa0, 0x03, 0xc0, 0x41, 0x82, 0x31, 0x8c, 0x0d, 0xb0, 0xff, 0xfe,
+    0xff, 0xfe, 0x0d, 0xb0, 0x31, 0x8c, 0x41, 0x82, 0x03, 0xc0, 0x05, 0xa0, 0x09, 0x90, 0x01, 0x80,
+];
+const IDLE_ICON: [u8; 32] = [
+    0x00, 0x00, 0x07, 0xe0, 0x18, 0x18, 0x20, 0x04, 0x40, 0x02, 0x40, 0x02, 0x80, 0x01, 0x9f, 0xf9,
+    0x9f, 0xf9, 0x80, 0x01, 0x40, 0x02, 0x40, 0x02, 0x20, 0x04, 0x18, 0x18, 0x07, 0xe0, 0x00, 0x00,
+];
+const FAULT_ICON: [u8; 32] = [
+    0x01, 0x80, 0x01, 0x80, 0x03, 0xc0, 0x02, 0x40, 0x06, 0x60, 0x05, 0xa0, 0x0d, 0xb0, 0x09, 0x90,
+    0x19, 0x98, 0x11, 0x88, 0x31, 0x8c, 0x20, 0x04, 0x61, 0x86, 0x41, 0x82, 0xff, 0xff, 0xff, 0xff,
+];
+
+// 8 x 8 status bar icons
+const STEP_ICON: [u8; 8] = [0x00, 0x03, 0x03, 0x0f, 0x0f, 0x3f, 0x3f, 0xff];
+const LINK_ICON: [u8; 8] = [0x18, 0x3c, 0x18, 0x99, 0xdb, 0x7e, 0x18, 0x3c];
+
+// Seven segment digits for the home screen temperature
+const DIGIT_WIDTH: i32 = 16;
+const DIGIT_HEIGHT: i32 = 32;
+const SEGMENT: i32 = 4;             // Segment thickness
+const DIGIT_GAP: i32 = 4;
+// Segments lit for 0 - 9 as bits gfedcba
+const DIGIT_SEGMENTS: [u8; 10] = [0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F];
+const MINUS_SEGMENTS: u8 = 0x40;
+
+pub const DEFAULT_I2C_ADDRESS: u8 = 0x3C;   // SA0 tied low
+const GRAPH_MIN_SPAN: f32 = 1.0;            // Smallest temperature range the graph is scaled to (degrees)
+
+/// The SPI device the display uses when it is wired to SPI1
+#[cfg(feature = "rp2040")]
+pub type DisplaySpi<'a> = ExclusiveDevice<Spi<'a, SPI1, Async>, Output<'a>, Delay>;
+
+/// The display as wired on the board: SPI1 or an I2C bus, with the reset line on a GPIO
+#[cfg(feature = "rp2040")]
+pub type BoardDisplay<'a, I2C, P> = Display<DisplayInterface<DisplaySpi<'a>, Output<'a>, I2C>, Output<'a>, P>;
+
+/// Bus the display module is wired to, chosen by the board configuration
+pub enum DisplayInterface<SPI, DC, I2C> {
+    Spi(SPIInterface<SPI, DC>),
+    I2c(I2CInterface<I2C>),
+}
+
+impl<SPI, DC, I2C> AsyncWriteOnlyDataCommand for DisplayInterface<SPI, DC, I2C>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+    I2C: I2c,
+{
+    async fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+        match self {
+            DisplayInterface::Spi(interface) => interface.send_commands(cmd).await,
+            DisplayInterface::I2c(interface) => interface.send_commands(cmd).await,
+        }
+    }
+
+    async fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+        match self {
+            DisplayInterface::Spi(interface) => interface.send_data(buf).await,
+            DisplayInterface::I2c(interface) => interface.send_data(buf).await,
+        }
+    }
+}
+
+#[cfg(feature = "rp2040")]
 pub struct DisplayPeripherals<'a, CLK, MOSI, SPI, DMA> {
     pub dc: Output<'a>,
     pub cs: Output<'a>,
@@ -35,6 +119,7 @@ pub struct DisplayPeripherals<'a, CLK, MOSI, SPI, DMA> {
     pub tx_dma: DMA,
 }
 
+#[cfg(feature = "rp2040")]
 impl<'a, CLK, MOSI, SPI, DMA> DisplayPeripherals<'a, CLK, MOSI, SPI, DMA> {
     pub fn new(
         dc: Output<'a>,
@@ -57,17 +142,408 @@ impl<'a, CLK, MOSI, SPI, DMA> DisplayPeripherals<'a, CLK, MOSI, SPI, DMA> {
     }
 }
 
-pub struct Display<'a> {
-    display: SH1107<
-        ExclusiveDevice<Spi<'a, SPI1, Async>, Output<'a>, Delay>,
-        Output<'a>,
-        Output<'a>
-    >,
+/// How far the display has gone towards sleep since the last key press
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum IdleStage {
+    Awake,
+    Dimmed,     // The current screen at low contrast
+    Clock,      // The minimal clock screen at low contrast
+    Asleep,     // The panel switched off
+}
+
+/// What the display does when no key has been pressed for a while.
+/// Every screen is also moved up to `max_shift` pixels each time it is drawn to spread the wear on the panel.
+#[derive(Copy, Clone)]
+pub struct IdlePolicy {
+    pub dim_after: u64,             // Seconds before the contrast is turned down
+    pub dim_contrast: u8,
+    pub clock_after: u64,           // Seconds before switching to the clock screen
+    pub sleep_after: Option<u64>,   // Seconds before switching the panel off, None to keep showing the clock
+    pub max_shift: i32,             // Pixels
+}
+
+impl IdlePolicy {
+    pub const DEFAULT: Self = Self { dim_after: 30, dim_contrast: 0x08, clock_after: 300, sleep_after: None, max_shift: 2 };
+
+    /// The stage for the time since the last key press
+    pub fn stage(&self, idle: u64) -> IdleStage {
+        match self.sleep_after {
+            Some(after) if idle >= after => IdleStage::Asleep,
+            _ if idle >= self.clock_after => IdleStage::Clock,
+            _ if idle >= self.dim_after => IdleStage::Dimmed,
+            _ => IdleStage::Awake,
+        }
+    }
+}
+
+// The order the picture is moved through, in steps of up to `max_shift`
+const SHIFT_PATH: [(i32, i32); 8] = [(0, 0), (1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (0, 2), (0, 1)];
+
+/// What the controller is doing, shown as an icon on the home screen
+#[derive(Copy, Clone, PartialEq)]
+pub enum HomeIcon {
+    Heating,
+    Cooling,
+    Idle,
+    Fault,
+}
+
+/// Everything shown on the home screen
+pub struct HomeScreen<'s> {
+    pub temp: Option<f32>,              // Degrees C, None while the sensor is faulty
+    pub target: f32,                    // Degrees C
+    pub icon: HomeIcon,
+    pub step: Option<(usize, usize)>,   // Profile step (from 1) and the number of steps, None without a profile
+    pub message: &'s str,
+    pub uptime: u64,                    // Seconds
+    pub link: bool,                     // USB is connected, so the serial console is available
+}
+
+/// The screens shown on the display. `DI` is the bus the module is wired to, `RST` its reset line
+/// and `P` the panel fitted. Screens are laid out as four lines spread over the panel's height.
+pub struct Display<DI, RST, P> {
+    display: Oled<DI, RST, P>,
     delay: Delay,
+    idle_policy: IdlePolicy,
+    idle: IdleStage,
+    shift_step: usize,      // Position along `SHIFT_PATH`
+    unit: TemperatureUnit,  // Temperatures are passed in degrees C and shown in this unit
 }
 
-impl<'a> Display<'a> {
-    pub fn new<CLK, MOSI, SPI, DMA>(
+impl<DI, RST, P> Display<DI, RST, P>
+where
+    DI: AsyncWriteOnlyDataCommand,
+    RST: OutputPin,
+    P: Panel,
+{
+    /// Display on any bus, with the reset line if the module has one
+    pub fn new(interface: DI, rst: Option<RST>) -> Self {
+        Self {
+            display: Oled::new(interface, rst),
+            delay: Delay,
+            idle_policy: IdlePolicy::DEFAULT,
+            idle: IdleStage::Awake,
+            shift_step: 0,
+            unit: TemperatureUnit::Celsius,
+        }
+    }
+
+    /// Height of a text line, the screen is split into four
+    fn line_height(&self) -> i32 {
+        self.display.bounding_box().size.height as i32 / 4
+    }
+
+    /// Top of a text line (0 - 3)
+    fn line_top(&self, line: i32) -> i32 {
+        line * self.line_height()
+    }
+
+    /// Baseline of a text line (0 - 3), with the 13 pixel font centred in the line
+    fn baseline(&self, line: i32) -> i32 {
+        self.line_top(line) + (self.line_height() + 4) / 2
+    }
+
+    /// Blank a text line (0 - 3)
+    async fn clear_line(&mut self, line: i32) {
+        let size = Size::new(self.display.bounding_box().size.width, self.line_height() as u32);
+        let _ = self.display.draw_rectangle(Point::new(0, self.line_top(line)), size, BinaryColor::Off, true).await;
+    }
+
+    /// Whether the screens fit the picture at a rotation. They are laid out for a landscape picture, so on a
+    /// 128 x 64 panel turning it a quarter would leave them 64 pixels wide and clipped.
+    pub const fn supports_rotation(rotation: Rotation) -> bool {
+        matches!(rotation, Rotation::Deg0 | Rotation::Deg180) || P::WIDTH == P::HEIGHT
+    }
+
+    /// Rotate and mirror the screens to suit how the panel is mounted. Takes effect from the next screen drawn.
+    /// A rotation the screens don't fit is refused and the orientation left as it was.
+    pub fn set_orientation(&mut self, rotation: Rotation, mirror: bool) -> Result<(), DisplayError> {
+        if !Self::supports_rotation(rotation) {
+            return Err(DisplayError::OutOfBoundsError);
+        }
+        self.display.set_orientation(rotation, mirror);
+        Ok(())
+    }
+
+    pub fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.idle_policy = policy;
+    }
+
+    pub fn set_temperature_unit(&mut self, unit: TemperatureUnit) {
+        self.unit = unit;
+    }
+
+    pub fn idle_stage(&self) -> IdleStage {
+        self.idle
+    }
+
+    /// Move to an idle stage, dimming or switching the panel off and back on with its own commands.
+    /// The caller draws the screen for the new stage.
+    pub async fn set_idle_stage(&mut self, stage: IdleStage) {
+        if stage == self.idle {
+            return;
+        }
+        if self.idle == IdleStage::Asleep {
+            let _ = self.display.on().await;
+        }
+        let _ = match stage {
+            IdleStage::Awake => self.display.set_contrast(P::CONTRAST).await,
+            IdleStage::Dimmed | IdleStage::Clock => self.display.set_contrast(self.idle_policy.dim_contrast).await,
+            IdleStage::Asleep => self.display.off().await,
+        };
+        self.idle = stage;
+    }
+
+    /// Size to lay out a screen that moves along `SHIFT_PATH` in, leaving a margin on the right and bottom so
+    /// nothing is pushed off the panel
+    fn shifted_size(&self) -> Size {
+        let margin = self.idle_policy.max_shift.max(0) as u32;
+        self.display.bounding_box().size.saturating_sub(Size::new(margin, margin))
+    }
+
+    /// Clear the screen and move the picture to its next position
+    async fn begin_screen(&mut self) {
+        self.shift_step = (self.shift_step + 1) % SHIFT_PATH.len();
+        let (x, y) = SHIFT_PATH[self.shift_step];
+        let max = self.idle_policy.max_shift;
+        let _ = self.display.clear().await;
+        self.display.set_shift(Point::new(x.min(max), y.min(max)));
+    }
+
+    /// Reset and set up the panel. An error means it didn't answer, the other calls then fail without drawing.
+    pub async fn initialise(&mut self) -> Result<(), DisplayError> {
+        self.display.init(&mut self.delay).await
+    }
+
+    pub async fn clear_all(&mut self) {
+        let _ = self.display.clear().await;
+    }
+
+    pub async fn clear_line_4(&mut self) {
+        self.clear_line(3).await;
+    }
+
+    pub async fn refresh_line_4(&mut self, text: &str) {
+        let _ = self.clear_line_4().await;
+        let _ = self.display.draw_text(text, Point::new(0, self.baseline(3)), BinaryColor::On).await;
+    }
+
+    pub async fn show(&mut self) {
+        let _ = self.display.show().await;
+    }
+
+    pub async fn show_splash_screen(&mut self) {
+        let _ = self.display.clear().await;
+        let _ = self.display.show().await;
+        self.draw_splash_screen().await;
+        Timer::after(Duration::from_millis(10)).await;
+        let _ = self.display.show().await;
+        Timer::after(Duration::from_millis(5000)).await;
+        let _ = self.display.clear().await;
+        let _ = self.display.show().await;
+        Timer::after(Duration::from_millis(10)).await;
+    }
+
+    /// The splash screen's picture, drawn into the framebuffer without sending it
+    async fn draw_splash_screen(&mut self) {
+        let size = self.display.bounding_box().size;
+        let middle = size.height as i32 / 2 - 32;  // Centre the 64 pixel high layout on taller screens
+        let _ = self.display.draw_rectangle(Point::new(0, 0), size, BinaryColor::On, false).await;
+        let _ = self.display.draw_text("  AutoBrew     ", Point::new(0, middle + 22), BinaryColor::On).await;
+        let _ = self.display.draw_text("    v0.1.0     ", Point::new(0, middle + 40), BinaryColor::On).await;
+        let _ = self.display.draw_image(&RUST_LOGO, 32, Point::new(92, middle + 14)).await;
+    }
+
+    pub async fn show_diagnostics(&mut self, power: &str, errors: &str, enclosure: &str, msg: &str) {
+        let _ = self.display.clear().await;
+        let _ = self.display.draw_text("  Power:", Point::new(0, self.baseline(0)), BinaryColor::On).await;
+        let _ = self.display.draw_text(power, Point::new(64, self.baseline(0)), BinaryColor::On).await;
+        let _ = self.display.draw_text(" Errors:", Point::new(0, self.baseline(1)), BinaryColor::On).await;
+        let _ = self.display.draw_text(errors, Point::new(64, self.baseline(1)), BinaryColor::On).await;
+        let _ = self.display.draw_text("    Box:", Point::new(0, self.baseline(2)), BinaryColor::On).await;
+        let _ = self.display.draw_text(enclosure, Point::new(64, self.baseline(2)), BinaryColor::On).await;
+        let _ = self.refresh_line_4(msg).await;
+        let _ = self.display.show().await;
+    }
+
+    /// Beer temperature (solid) and target (dotted) over the last `hours`, newest on the right.
+    /// Bands along the bottom show when the heating (solid) or cooling (dashed) relay was on.
+    pub async fn show_graph<const N: usize>(&mut self, history: &History<N>, hours: u64) {
+        self.begin_screen().await;
+        let size = self.shifted_size();
+        let (width, height) = (size.width as i32, size.height as i32);
+        let Some((low, high)) = history.range(width as usize, GRAPH_MIN_SPAN) else {
+            let _ = self.display.draw_text(" No history yet", Point::new(0, self.baseline(1)), BinaryColor::On).await;
+            let _ = self.display.show().await;
+            return;
+        };
+
+        // Header with the time span and the vertical scale
+        let mut header: String<24> = String::new();
+        let _ = write!(&mut header, "{}h {:.1}-{:.1}{}", hours, self.unit.from_celsius(low), self.unit.from_celsius(high), self.unit.symbol());
+        let _ = self.display.draw_text(header.as_str(), Point::new(0, 10), BinaryColor::On).await;
+
+        let plot_top = 14;
+        let plot_bottom = height - 6;   // Leaves room for the relay bands
+        let band = height - 2;
+        let scale = (plot_bottom - plot_top) as f32 / (high - low);
+        let to_y = |temp: f32| plot_bottom - ((temp - low) * scale) as i32;
+
+        let count = history.len().min(width as usize) as i32;
+        let mut last: Option<Point> = None;
+        for (i, sample) in history.latest(width as usize).enumerate() {
+            let x = width - count + i as i32;
+            let point = Point::new(x, to_y(sample.temp));
+            let _ = self.display.draw_line(last.unwrap_or(point), point, BinaryColor::On).await;
+            last = Some(point);
+            if x % 2 == 0 {
+                let _ = self.display.draw_pixel(Point::new(x, to_y(sample.target)), BinaryColor::On).await;
+            }
+            if sample.heating || (sample.cooling && x % 4 < 2) {
+                let _ = self.display.draw_line(Point::new(x, band - 1), Point::new(x, band + 1), BinaryColor::On).await;
+            }
+        }
+        let _ = self.display.show().await;
+    }
+
+    /// The lines rendered by a `Menu`, one per text line
+    pub async fn show_menu(&mut self, lines: &[String<16>]) {
+        let _ = self.display.clear().await;
+        for (line, text) in lines.iter().enumerate() {
+            let _ = self.display.draw_text(text.as_str(), Point::new(0, self.baseline(line as i32)), BinaryColor::On).await;
+        }
+        let _ = self.display.show().await;
+    }
+
+    /// The current temperature in large digits with the target and an icon for what the controller is doing beside it.
+    /// Below are a message line and a status bar with the uptime, the profile step and the serial link.
+    pub async fn show_home(&mut self, home: &HomeScreen<'_>) {
+        self.begin_screen().await;
+        let size = self.shifted_size();
+        let (width, height) = (size.width as i32, size.height as i32);
+        let status_top = height - 11;
+        let big_top = (status_top - 15 - DIGIT_HEIGHT).max(0) / 2;
+
+        let mut temp: String<8> = String::new();
+        match home.temp {
+            Some(value) => { let _ = write!(&mut temp, "{:.1}", self.unit.from_celsius(value)); }
+            None => { let _ = temp.push_str("--.-"); }
+        }
+        self.draw_big_number(temp.as_str(), Point::new(0, big_top)).await;
+
+        let icon = match home.icon {
+            HomeIcon::Heating => &HEATING_ICON,
+            HomeIcon::Cooling => &COOLING_ICON,
+            HomeIcon::Idle => &IDLE_ICON,
+            HomeIcon::Fault => &FAULT_ICON,
+        };
+        let _ = self.display.draw_image(icon, 16, Point::new(width - 21, big_top)).await;
+        let mut target: String<8> = String::new();
+        let _ = write!(&mut target, "{:.1}{}", self.unit.from_celsius(home.target), self.unit.symbol());
+        let _ = self.display.draw_small_text(target.as_str(), Point::new(width - 6 * target.len() as i32, big_top + DIGIT_HEIGHT - 2), BinaryColor::On).await;
+
+        // Message line, centred
+        let message = home.message.trim();
+        let x = ((width - 6 * message.len() as i32) / 2).max(0);
+        let _ = self.display.draw_small_text(message, Point::new(x, status_top - 5), BinaryColor::On).await;
+
+        // Status bar
+        let _ = self.display.draw_line(Point::new(0, status_top - 2), Point::new(width - 1, status_top - 2), BinaryColor::On).await;
+        let mut uptime: String<12> = String::new();
+        let (days, hours, minutes) = (home.uptime / 86400, home.uptime / 3600 % 24, home.uptime / 60 % 60);
+        let _ = if days > 0 {
+            write!(&mut uptime, "{}d{:02}h", days, hours)
+        } else {
+            write!(&mut uptime, "{:02}:{:02}", hours, minutes)
+        };
+        let _ = self.display.draw_small_text(uptime.as_str(), Point::new(0, height - 2), BinaryColor::On).await;
+        if let Some((step, steps)) = home.step {
+            let mut text: String<8> = String::new();
+            let _ = write!(&mut text, "{}/{}", step, steps);
+            let _ = self.display.draw_image(&STEP_ICON, 8, Point::new(width / 2 - 12, height - 9)).await;
+            let _ = self.display.draw_small_text(text.as_str(), Point::new(width / 2 - 2, height - 2), BinaryColor::On).await;
+        }
+        if home.link {
+            let _ = self.display.draw_image(&LINK_ICON, 8, Point::new(width - 10, height - 9)).await;
+        }
+        let _ = self.display.show().await;
+    }
+
+    /// Minimal screen for when the controller is left alone: the time since start up in large digits, labelled so it
+    /// isn't mistaken for the time of day, with the temperature under it. `temp` is in degrees C and drawn in the
+    /// unit set with `set_temperature_unit`.
+    pub async fn show_clock(&mut self, uptime: u64, temp: Option<f32>) {
+        self.begin_screen().await;
+        let size = self.shifted_size();
+        let (width, height) = (size.width as i32, size.height as i32);
+        let (days, hours, minutes) = (uptime / 86400, uptime / 3600 % 24, uptime / 60 % 60);
+        let mut label: String<16> = String::new();
+        let _ = if days > 0 { write!(&mut label, "Uptime {}d", days) } else { write!(&mut label, "Uptime") };
+        let mut clock: String<8> = String::new();
+        let _ = write!(&mut clock, "{:02}:{:02}", hours, minutes);
+        let clock_width = 4 * (DIGIT_WIDTH + DIGIT_GAP) + SEGMENT;
+        let label_top = (height - DIGIT_HEIGHT - 24) / 2;  // 11 pixels for the label above the digits and 13 for the temperature below
+        let top = label_top + 11;
+        let x = (width - 6 * label.len() as i32) / 2;
+        let _ = self.display.draw_small_text(label.as_str(), Point::new(x, label_top + 7), BinaryColor::On).await;
+        self.draw_big_number(clock.as_str(), Point::new((width - clock_width) / 2, top)).await;
+        if let Some(temp) = temp {
+            let mut text: String<8> = String::new();
+            let _ = write!(&mut text, "{:.1}{}", self.unit.from_celsius(temp), self.unit.symbol());
+            let x = (width - 6 * text.len() as i32) / 2;
+            let _ = self.display.draw_small_text(text.as_str(), Point::new(x, top + DIGIT_HEIGHT + 11), BinaryColor::On).await;
+        }
+        let _ = self.display.show().await;
+    }
+
+    /// Digits, '-', '.' and ':' drawn as seven segment characters
+    async fn draw_big_number(&mut self, text: &str, top_left: Point) {
+        let mut x = top_left.x;
+        let y = top_left.y;
+        for character in text.chars() {
+            let dot = Size::new(SEGMENT as u32, SEGMENT as u32);
+            if character == '.' {
+                let _ = self.display.draw_rectangle(Point::new(x, y + DIGIT_HEIGHT - SEGMENT), dot, BinaryColor::On, true).await;
+                x += SEGMENT + DIGIT_GAP;
+                continue;
+            }
+            if character == ':' {
+                let _ = self.display.draw_rectangle(Point::new(x, y + DIGIT_HEIGHT / 3 - SEGMENT / 2), dot, BinaryColor::On, true).await;
+                let _ = self.display.draw_rectangle(Point::new(x, y + 2 * DIGIT_HEIGHT / 3 - SEGMENT / 2), dot, BinaryColor::On, true).await;
+                x += SEGMENT + DIGIT_GAP;
+                continue;
+            }
+            let segments = match character {
+                '-' => MINUS_SEGMENTS,
+                digit => digit.to_digit(10).map_or(0, |digit| DIGIT_SEGMENTS[digit as usize]),
+            };
+            let half = DIGIT_HEIGHT / 2;
+            let across = Size::new((DIGIT_WIDTH - 2 * SEGMENT) as u32, SEGMENT as u32);
+            let down = Size::new(SEGMENT as u32, (half - SEGMENT - SEGMENT / 2) as u32);
+            let shapes = [
+                (Point::new(x + SEGMENT, y), across),                                       // a
+                (Point::new(x + DIGIT_WIDTH - SEGMENT, y + SEGMENT), down),                 // b
+                (Point::new(x + DIGIT_WIDTH - SEGMENT, y + half + SEGMENT / 2), down),      // c
+                (Point::new(x + SEGMENT, y + DIGIT_HEIGHT - SEGMENT), across),              // d
+                (Point::new(x, y + half + SEGMENT / 2), down),                              // e
+                (Point::new(x, y + SEGMENT), down),                                         // f
+                (Point::new(x + SEGMENT, y + half - SEGMENT / 2), across),                  // g
+            ];
+            for (segment, (corner, size)) in shapes.into_iter().enumerate() {
+                if segments & (1 << segment) != 0 {
+                    let _ = self.display.draw_rectangle(corner, size, BinaryColor::On, true).await;
+                }
+            }
+            x += DIGIT_WIDTH + DIGIT_GAP;
+        }
+    }
+}
+
+/// Constructors for the display as wired on the board
+#[cfg(feature = "rp2040")]
+impl<'a, I2C: I2c, P: Panel> BoardDisplay<'a, I2C, P> {
+    /// Display on SPI1
+    pub fn new_spi<CLK, MOSI, SPI, DMA>(
         display_peripherals: DisplayPeripherals<'a, CLK, MOSI, SPI, DMA>
     ) -> Self
     where
@@ -109,93 +585,195 @@ impl<'a> Display<'a> {
             ).unwrap();
 
         // Initialize the display 
-        let display = SH1107::new(
-            spi_device,
-            dc,
-            rst,
-        );
-        
-        Self {
-            display,
-            delay,
+        Self::new(DisplayInterface::Spi(SPIInterface::new(spi_device, dc)), Some(rst))
+    }
+
+    /// Display on an I2C bus, with the reset line if the module has one
+    pub fn new_i2c(i2c: I2C, address: u8, rst: Option<Output<'a>>) -> Self {
+        let interface = I2CInterface::new(i2c, address, 0x40);  // 0x40 = control byte for data
+        Self::new(DisplayInterface::I2c(interface), rst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::HistorySample;
+    use crate::main_menu::{main_menu, Screen, MENU_ENTRIES, MENU_TARGET};
+    use crate::menu::{Entry, Key, Menu};
+    use crate::mock::{MockInterface, MockPin};
+    use crate::panel::{Sh1107x128, Sh1107x64};
+    use embassy_futures::block_on;
+    use std::{env, format, fs, path::Path};
+
+    type TestDisplay<P> = Display<MockInterface, MockPin, P>;
+
+    fn display<P: Panel>() -> TestDisplay<P> {
+        Display::new(MockInterface::default(), None)
+    }
+
+    /// The picture on the panel as a plain PBM (1 = lit), one line per row of pixels
+    fn pbm<P: Panel>(display: &TestDisplay<P>) -> std::string::String {
+        let mut pbm = format!("P1\n{} {}\n", P::WIDTH, P::HEIGHT);
+        for y in 0..P::HEIGHT as usize {
+            for x in 0..P::WIDTH as usize {
+                pbm.push(if display.display.panel_pixel(x, y) { '1' } else { '0' });
+            }
+            pbm.push('\n');
         }
+        pbm
     }
 
-    pub async fn initialise(&mut self) {
-        self.display.init(&mut self.delay).await.unwrap();
+    /// Compare the panel against tests/golden/`name`.pbm.
+    /// Run with UPDATE_GOLDENS=1 to write the goldens from what is drawn now, after checking the change is wanted.
+    fn check_golden<P: Panel>(display: &TestDisplay<P>, name: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.pbm", name));
+        let actual = pbm(display);
+        if env::var_os("UPDATE_GOLDENS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("{} is missing, run with UPDATE_GOLDENS=1 to create it", path.display()));
+        if actual != expected {
+            let actual_path = env::temp_dir().join(format!("{}.pbm", name));
+            fs::write(&actual_path, actual).unwrap();
+            panic!("{} doesn't match {}, the picture drawn is in {}", name, path.display(), actual_path.display());
+        }
     }
 
-    pub async fn clear_all(&mut self) {
-        let _ = self.display.clear().await;
+    fn readings() -> HomeScreen<'static> {
+        HomeScreen {
+            temp: Some(19.4),
+            target: 19.0,
+            icon: HomeIcon::Heating,
+            step: Some((2, 3)),
+            message: "Humidity 65 %RH",
+            uptime: 3 * 3600 + 25 * 60,
+            link: true,
+        }
     }
 
-    pub async fn clear_line_1(&mut self) {
-        let _ = self.display.draw_rectangle(Point::new(0, 0), Size::new(128, 16), BinaryColor::Off, true).await;
+    // The firmware's menu, for the 128 x 64 panel the fixtures are drawn on
+    const MENU: [Entry<Screen>; MENU_ENTRIES] = main_menu(false, 11.0, 27.0, TemperatureUnit::Celsius);
+
+    fn menu_at(index: usize) -> Menu<Screen> {
+        let mut menu = Menu::new(&MENU);
+        menu.set_value(MENU_TARGET, crate::menu::Value::Number(19.0));
+        menu.open();
+        for _ in 0..index {
+            menu.handle(Key::Next);
+        }
+        menu
     }
 
-    pub async fn clear_line_2(&mut self) {
-        let _ = self.display.draw_rectangle(Point::new(0, 16), Size::new(128, 16), BinaryColor::Off, true).await;
+    #[test]
+    fn splash() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.draw_splash_screen());
+        check_golden(&display, "splash");
     }
 
-    pub async fn clear_line_3(&mut self) {
-        let _ = self.display.draw_rectangle(Point::new(0, 32), Size::new(128, 16), BinaryColor::Off, true).await;
+    #[test]
+    fn diagnostics() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_diagnostics("EXTERNAL", "0", "24.5", ""));
+        check_golden(&display, "diagnostics");
     }
 
-    pub async fn clear_line_4(&mut self) {
-        let _ = self.display.draw_rectangle(Point::new(0, 48), Size::new(128, 16), BinaryColor::Off, true).await;
+    #[test]
+    fn home_readings() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_home(&readings()));
+        check_golden(&display, "home_readings");
     }
 
-    pub async fn refresh_line_1(&mut self, text: &str) {
-        let _ = self.clear_line_1().await;
-        let display_line = " Current: ";
-        let _ = self.display.draw_text(display_line, Point::new(0, 10), BinaryColor::On).await;
-        let _ = self.display.draw_text(text, Point::new(80, 10), BinaryColor::On).await;
+    #[test]
+    fn home_sensor_fault() {
+        let mut display = display::<Sh1107x64>();
+        let home = HomeScreen { temp: None, icon: HomeIcon::Fault, step: None, message: "SENSOR NOT FOUND", link: false, ..readings() };
+        block_on(display.show_home(&home));
+        check_golden(&display, "home_sensor_fault");
     }
 
-    pub async fn refresh_line_2(&mut self, text: &str) {
-        let _ = self.clear_line_2().await;
-        let display_line = "  Target: ";
-        let _ = self.display.draw_text(display_line, Point::new(0, 26), BinaryColor::On).await;
-        let _ = self.display.draw_text(text, Point::new(80, 26), BinaryColor::On).await;
+    #[test]
+    fn home_readings_in_fahrenheit() {
+        let mut display = display::<Sh1107x64>();
+        display.set_temperature_unit(TemperatureUnit::Fahrenheit);
+        block_on(display.show_home(&readings()));
+        check_golden(&display, "home_readings_fahrenheit");
     }
 
-    pub async fn refresh_line_3(&mut self, text: &str) {
-        let _ = self.clear_line_3().await;
-        let display_line = "    Diff: ";
-        let _ = self.display.draw_text(display_line, Point::new(0, 42), BinaryColor::On).await;
-        let _ = self.display.draw_text(text, Point::new(80, 42), BinaryColor::On).await;
+    #[test]
+    fn home_readings_square_panel_turned_90() {
+        let mut display = display::<Sh1107x128>();
+        display.set_orientation(Rotation::Deg90, false).unwrap();
+        block_on(display.show_home(&readings()));
+        check_golden(&display, "home_readings_square_90");
     }
 
-    pub async fn refresh_line_4(&mut self, text: &str) {
-        let _ = self.clear_line_4().await;
-        let _ = self.display.draw_text(text, Point::new(0, 58), BinaryColor::On).await;
+    #[test]
+    fn missing_panel_fails_to_initialise() {
+        let mut missing: TestDisplay<Sh1107x64> = Display::new(MockInterface { absent: true }, None);
+        assert!(block_on(missing.initialise()).is_err());
+        assert!(block_on(display::<Sh1107x64>().initialise()).is_ok());
     }
 
-    pub async fn show(&mut self) {
-        let _ = self.display.show().await;
+    #[test]
+    fn quarter_turns_are_refused_on_a_wide_panel() {
+        let mut display = display::<Sh1107x64>();
+        assert!(display.set_orientation(Rotation::Deg90, false).is_err());
+        assert!(display.set_orientation(Rotation::Deg180, true).is_ok());
     }
 
-    pub async fn show_splash_screen(&mut self) {
-        let _ = self.display.clear().await;
-        let _ = self.display.show().await;
-        let _ = self.display.draw_rectangle(Point::new(0, 0), Size::new(128, 64), BinaryColor::On, false).await;
-        let _ = self.display.draw_text("  AutoBrew     ", Point::new(0, 22), BinaryColor::On).await;
-        let _ = self.display.draw_text("    v0.1.0     ", Point::new(0, 40), BinaryColor::On).await;
-        let _ = self.display.draw_image(&RUST_LOGO, 32, Point::new(92, 14)).await;
-        Timer::after(Duration::from_millis(10)).await;
-        let _ = self.display.show().await;
-        Timer::after(Duration::from_millis(5000)).await;
-        let _ = self.display.clear().await;
-        let _ = self.display.show().await;
-        Timer::after(Duration::from_millis(10)).await;
+    #[test]
+    fn clock() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_clock(26 * 3600 + 7 * 60, Some(19.4)));
+        check_golden(&display, "clock");
     }
 
-    pub async fn refresh_readings(&mut self, cur_tmp: &str, tar_tmp: &str, cur_var: &str, msg: &str) {
-            let _ = self.refresh_line_1(cur_tmp).await;
-            let _ = self.refresh_line_2(tar_tmp).await;
-            let _ = self.refresh_line_3(cur_var).await;
-            let _ = self.refresh_line_4(msg).await;
-            let _ = self.display.show().await;
+    #[test]
+    fn graph() {
+        let mut display = display::<Sh1107x64>();
+        let mut history = History::<128>::new();
+        for i in 0..100 {
+            let temp = 18.0 + (i as f32 / 12.0).sin() * 1.5;
+            history.push(HistorySample { temp, target: 19.0, heating: temp < 18.5, cooling: temp > 19.5 });
+        }
+        block_on(display.show_graph(&history, 12));
+        check_golden(&display, "graph");
+    }
+
+    #[test]
+    fn graph_without_history() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_graph(&History::<128>::new(), 12));
+        check_golden(&display, "graph_empty");
+    }
+
+    #[test]
+    fn menu_first_page() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_menu(&menu_at(0).render()));
+        check_golden(&display, "menu_first_page");
+    }
+
+    #[test]
+    fn menu_last_page() {
+        let mut display = display::<Sh1107x64>();
+        block_on(display.show_menu(&menu_at(MENU.len() - 1).render()));
+        check_golden(&display, "menu_last_page");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn menu_editing() {
+        let mut display = display::<Sh1107x64>();
+        let mut menu = menu_at(MENU_TARGET);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        block_on(display.show_menu(&menu.render()));
+        check_golden(&display, "menu_editing");
+    }
+}
@@ -0,0 +1,250 @@
+use embedded_hal_async::i2c::I2c;
+
+use crate::onewire::{OneWire, Triplet};
+use crate::sensor::SensorError;
+
+pub const DEFAULT_ADDRESS: u8 = 0x18;   // AD0/AD1 (and AD2 on the DS2482-800) tied low
+
+const DEVICE_RESET: u8 = 0xF0;
+const SET_READ_POINTER: u8 = 0xE1;
+const WRITE_CONFIG: u8 = 0xD2;
+const CHANNEL_SELECT: u8 = 0xC3;
+const ONEWIRE_RESET: u8 = 0xB4;
+const ONEWIRE_WRITE_BYTE: u8 = 0xA5;
+const ONEWIRE_READ_BYTE: u8 = 0x96;
+const ONEWIRE_TRIPLET: u8 = 0x78;
+
+const DATA_REGISTER: u8 = 0xE1;     // Read pointer code (every 1-Wire command moves the pointer back to the status register)
+
+const STATUS_BUSY: u8 = 0x01;       // 1-Wire busy
+const STATUS_PRESENCE: u8 = 0x02;   // Presence pulse detected
+const STATUS_SHORT: u8 = 0x04;      // Short detected
+const STATUS_RESET: u8 = 0x10;      // Device reset has happened
+const STATUS_ID_BIT: u8 = 0x20;     // Single bit result / first bit of a triplet
+const STATUS_COMPLEMENT_BIT: u8 = 0x40; // Second bit of a triplet
+const STATUS_DIRECTION: u8 = 0x80;  // Branch direction taken by a triplet
+
+const CONFIG_ACTIVE_PULLUP: u8 = 0x01;
+const BUSY_POLLS: u8 = 100;         // Status reads before a 1-Wire operation is treated as stuck (a reset takes about 1.2ms)
+
+// Channel select codes and the value the configuration register reads back as for each channel of a DS2482-800
+const CHANNEL_CODES: [(u8, u8); 8] = [
+    (0xF0, 0xB8), (0xE1, 0xB1), (0xD2, 0xAA), (0xC3, 0xA3),
+    (0xB4, 0x9C), (0xA5, 0x95), (0x96, 0x8E), (0x87, 0x87),
+];
+
+/// DS2482-100 (single channel) or DS2482-800 (eight channels) I2C to 1-Wire bridge
+pub struct Ds2482<I2C> {
+    i2c: I2C,
+    address: u8,
+    channel: Option<u8>,    // DS2482-800 channel in use, None for a DS2482-100
+}
+
+impl<I2C: I2c> Ds2482<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self { i2c, address, channel: None }
+    }
+
+    /// Reset the bridge and turn on its active pull-up, which gives cleaner edges on long cables.
+    /// A reset puts a DS2482-800 back on channel 0, so the channel selected before is selected again.
+    pub async fn init(&mut self) -> Result<(), SensorError> {
+        self.i2c.write(self.address, &[DEVICE_RESET]).await.map_err(|_| SensorError::NoPresence)?;
+        if self.read_register().await? & STATUS_RESET == 0 {
+            return Err(SensorError::BusError);
+        }
+        self.write_config(CONFIG_ACTIVE_PULLUP).await?;
+        match self.channel {
+            Some(channel) => self.select_channel(channel).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Select the 1-Wire channel (0 - 7) used by the following operations. DS2482-800 only.
+    pub async fn select_channel(&mut self, channel: u8) -> Result<(), SensorError> {
+        let (code, read_back) = *CHANNEL_CODES.get(channel as usize).ok_or(SensorError::ConfigMismatch)?;
+        self.i2c.write(self.address, &[CHANNEL_SELECT, code]).await.map_err(|_| SensorError::BusError)?;
+        if self.read_register().await? != read_back {
+            return Err(SensorError::ConfigMismatch);
+        }
+        self.channel = Some(channel);
+        Ok(())
+    }
+
+    /// The DS2482-800 channel in use, None for a DS2482-100 or when no channel has been selected
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    /// The configuration register is written with the upper nibble as the complement of the lower
+    async fn write_config(&mut self, config: u8) -> Result<(), SensorError> {
+        let byte = (config & 0x0F) | ((!config & 0x0F) << 4);
+        self.i2c.write(self.address, &[WRITE_CONFIG, byte]).await.map_err(|_| SensorError::BusError)?;
+        if self.read_register().await? != config & 0x0F {
+            return Err(SensorError::ConfigMismatch);
+        }
+        Ok(())
+    }
+
+    /// Read the register the read pointer was left on by the last command
+    async fn read_register(&mut self) -> Result<u8, SensorError> {
+        let mut data = [0u8; 1];
+        self.i2c.read(self.address, &mut data).await.map_err(|_| SensorError::BusError)?;
+        Ok(data[0])
+    }
+
+    /// Send a 1-Wire command and poll the status register until the bridge has finished it
+    async fn command(&mut self, bytes: &[u8]) -> Result<u8, SensorError> {
+        self.i2c.write(self.address, bytes).await.map_err(|_| SensorError::BusError)?;
+        for _ in 0..BUSY_POLLS {
+            let status = self.read_register().await?;
+            if status & STATUS_BUSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(SensorError::BusError)
+    }
+}
+
+impl<I2C: I2c> OneWire for Ds2482<I2C> {
+    async fn reset(&mut self) -> Result<bool, SensorError> {
+        let status = self.command(&[ONEWIRE_RESET]).await?;
+        if status & STATUS_SHORT != 0 {
+            return Err(SensorError::BusShorted);
+        }
+        Ok(status & STATUS_PRESENCE != 0)
+    }
+
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SensorError> {
+        for byte in bytes {
+            self.command(&[ONEWIRE_WRITE_BYTE, *byte]).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SensorError> {
+        for byte in buffer.iter_mut() {
+            self.command(&[ONEWIRE_READ_BYTE]).await?;
+            self.i2c.write(self.address, &[SET_READ_POINTER, DATA_REGISTER]).await.map_err(|_| SensorError::BusError)?;
+            *byte = self.read_register().await?;
+        }
+        Ok(())
+    }
+
+    async fn triplet(&mut self, direction: bool) -> Result<Triplet, SensorError> {
+        let status = self.command(&[ONEWIRE_TRIPLET, if direction { 0x80 } else { 0x00 }]).await?;
+        Ok(Triplet {
+            id_bit: status & STATUS_ID_BIT != 0,
+            complement_bit: status & STATUS_COMPLEMENT_BIT != 0,
+            direction: status & STATUS_DIRECTION != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockI2c;
+    use embassy_futures::block_on;
+
+    fn bridge(reads: &[&[u8]]) -> Ds2482<MockI2c> {
+        Ds2482::new(MockI2c::new(reads), DEFAULT_ADDRESS)
+    }
+
+    #[test]
+    fn init_resets_and_turns_on_the_active_pullup() {
+        let mut bridge = bridge(&[&[STATUS_RESET], &[CONFIG_ACTIVE_PULLUP]]);
+        block_on(bridge.init()).unwrap();
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![DEVICE_RESET]), (0x18, vec![WRITE_CONFIG, 0xE1])]);
+    }
+
+    #[test]
+    fn init_without_the_reset_flag_is_a_bus_error() {
+        let mut bridge = bridge(&[&[0x00]]);
+        assert_eq!(block_on(bridge.init()), Err(SensorError::BusError));
+    }
+
+    #[test]
+    fn missing_bridge_is_no_presence() {
+        let mut bridge = bridge(&[]);
+        bridge.i2c.absent = true;
+        assert_eq!(block_on(bridge.init()), Err(SensorError::NoPresence));
+    }
+
+    #[test]
+    fn config_that_does_not_read_back_is_a_mismatch() {
+        let mut bridge = bridge(&[&[STATUS_RESET], &[0x00]]);
+        assert_eq!(block_on(bridge.init()), Err(SensorError::ConfigMismatch));
+    }
+
+    #[test]
+    fn select_channel_sends_the_channel_code_and_checks_it() {
+        let mut bridge = bridge(&[&[0xA3]]);
+        block_on(bridge.select_channel(3)).unwrap();
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![CHANNEL_SELECT, 0xC3])]);
+        assert_eq!(bridge.channel(), Some(3));
+    }
+
+    #[test]
+    fn select_channel_rejects_a_wrong_read_back_or_channel() {
+        let mut bridge = bridge(&[&[0xB8]]);
+        assert_eq!(block_on(bridge.select_channel(3)), Err(SensorError::ConfigMismatch));
+        assert_eq!(block_on(bridge.select_channel(8)), Err(SensorError::ConfigMismatch));
+        assert_eq!(bridge.channel(), None);
+    }
+
+    #[test]
+    fn init_selects_the_channel_again_after_the_reset() {
+        let mut bridge = bridge(&[&[0x95], &[STATUS_RESET], &[CONFIG_ACTIVE_PULLUP], &[0x95]]);
+        block_on(bridge.select_channel(5)).unwrap();
+        block_on(bridge.init()).unwrap();
+        assert_eq!(bridge.i2c.writes.last(), Some(&(0x18, vec![CHANNEL_SELECT, 0xA5])));
+    }
+
+    #[test]
+    fn reset_waits_while_busy_and_reports_presence() {
+        let mut bridge = bridge(&[&[STATUS_BUSY], &[STATUS_BUSY | STATUS_PRESENCE], &[STATUS_PRESENCE]]);
+        assert_eq!(block_on(bridge.reset()), Ok(true));
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![ONEWIRE_RESET])]);
+    }
+
+    #[test]
+    fn reset_reports_no_presence_and_shorts() {
+        let mut bridge = bridge(&[&[0x00], &[STATUS_SHORT]]);
+        assert_eq!(block_on(bridge.reset()), Ok(false));
+        assert_eq!(block_on(bridge.reset()), Err(SensorError::BusShorted));
+    }
+
+    #[test]
+    fn stuck_busy_is_a_bus_error() {
+        let busy = [STATUS_BUSY];
+        let reads = [&busy[..]; BUSY_POLLS as usize];
+        let mut bridge = bridge(&reads);
+        assert_eq!(block_on(bridge.reset()), Err(SensorError::BusError));
+    }
+
+    #[test]
+    fn bytes_are_written_one_command_at_a_time() {
+        let mut bridge = bridge(&[&[0x00], &[0x00]]);
+        block_on(bridge.write_bytes(&[0xCC, 0x44])).unwrap();
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![ONEWIRE_WRITE_BYTE, 0xCC]), (0x18, vec![ONEWIRE_WRITE_BYTE, 0x44])]);
+    }
+
+    #[test]
+    fn read_byte_moves_the_pointer_to_the_data_register() {
+        let mut bridge = bridge(&[&[0x00], &[0x5A]]);
+        let mut buffer = [0u8; 1];
+        block_on(bridge.read_bytes(&mut buffer)).unwrap();
+        assert_eq!(buffer, [0x5A]);
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![ONEWIRE_READ_BYTE]), (0x18, vec![SET_READ_POINTER, DATA_REGISTER])]);
+    }
+
+    #[test]
+    fn triplet_sends_the_direction_and_decodes_the_status() {
+        let mut bridge = bridge(&[&[STATUS_COMPLEMENT_BIT], &[STATUS_ID_BIT | STATUS_COMPLEMENT_BIT | STATUS_DIRECTION]]);
+        let triplet = block_on(bridge.triplet(true)).unwrap();
+        assert!(!triplet.id_bit && triplet.complement_bit && !triplet.direction);
+        let triplet = block_on(bridge.triplet(false)).unwrap();
+        assert!(triplet.id_bit && triplet.complement_bit && triplet.direction);
+        assert_eq!(bridge.i2c.writes, [(0x18, vec![ONEWIRE_TRIPLET, 0x80]), (0x18, vec![ONEWIRE_TRIPLET, 0x00])]);
+    }
+}
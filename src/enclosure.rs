@@ -0,0 +1,40 @@
+use embassy_rp::adc::Channel;
+
+use crate::analog::{read_average, SharedAdc, ADC_MAX};
+use crate::sensor::SensorError;
+
+const ADC_REFERENCE: f32 = 3.3;     // ADC reference voltage
+const SENSOR_VOLTAGE_27C: f32 = 0.706;  // Temperature sensor output at 27 degrees C (RP2040 datasheet)
+const SENSOR_SLOPE: f32 = -0.001721;    // Change in output per degree C
+
+/// Watches the temperature inside the controller's enclosure using the RP2040's on-die sensor
+pub struct EnclosureMonitor<'d> {
+    adc: &'d SharedAdc<'d>,
+    channel: Channel<'d>,
+    limit: f32,         // Alarm is raised at or above this temperature (degrees C)
+    hysteresis: f32,    // Alarm clears this far below the limit
+    alarm: bool,
+}
+
+impl<'d> EnclosureMonitor<'d> {
+    pub fn new(adc: &'d SharedAdc<'d>, channel: Channel<'d>, limit: f32, hysteresis: f32) -> Self {
+        Self { adc, channel, limit, hysteresis, alarm: false }
+    }
+
+    /// Die temperature in degrees C
+    pub async fn temperature(&mut self) -> Result<f32, SensorError> {
+        let raw = read_average(self.adc, &mut self.channel, 16).await?;
+        let voltage = raw as f32 * ADC_REFERENCE / ADC_MAX as f32;
+        Ok(27.0 + (voltage - SENSOR_VOLTAGE_27C) / SENSOR_SLOPE)
+    }
+
+    /// Update the alarm state with a new temperature. Returns true while the alarm is raised.
+    pub fn check(&mut self, temp: f32) -> bool {
+        if temp >= self.limit {
+            self.alarm = true;
+        } else if temp < self.limit - self.hysteresis {
+            self.alarm = false;
+        }
+        self.alarm
+    }
+}
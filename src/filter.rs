@@ -0,0 +1,201 @@
+use heapless::HistoryBuffer;
+
+/// Settings for the reading filter pipeline
+#[derive(Copy, Clone)]
+pub struct FilterConfig {
+    pub ema_alpha: f32,     // Weight given to each new median value (1.0 = no smoothing)
+    pub max_rate: f32,      // Largest physically possible rate of change (degrees C per second)
+    pub max_step: f32,      // Largest change accepted between two readings however far apart they are (degrees C)
+    pub reseed_after: u32,  // Consecutive rejected readings that agree with each other before the filter restarts from them
+}
+
+/// A single reading after it has passed through the filter
+#[derive(Copy, Clone)]
+pub struct FilteredReading {
+    pub raw: f32,
+    pub filtered: f32,
+}
+
+/// Filter stage between the sensor and the controller.
+/// Readings pass through spike rejection, a median of the last `N` readings and then an exponential moving average.
+pub struct ReadingFilter<const N: usize> {
+    config: FilterConfig,
+    window: HistoryBuffer<f32, N>,
+    ema: Option<f32>,
+    last_accepted: Option<(f32, u64)>,  // Last accepted raw value and the time it was taken (ms)
+    last_rejected: Option<(f32, u64)>,  // Last rejected raw value and the time it was taken (ms)
+    rejected_run: u32,                  // Consecutive rejected readings that agree with each other
+    crc_failures: u32,
+    spikes_rejected: u32,
+}
+
+impl<const N: usize> ReadingFilter<N> {
+    pub fn new(config: FilterConfig) -> Self {
+        Self {
+            config,
+            window: HistoryBuffer::new(),
+            ema: None,
+            last_accepted: None,
+            last_rejected: None,
+            rejected_run: 0,
+            crc_failures: 0,
+            spikes_rejected: 0,
+        }
+    }
+
+    /// Feed a new raw reading taken at `now_ms` into the filter.
+    /// Returns `None` if the reading was rejected as a spike.
+    pub fn push(&mut self, raw: f32, now_ms: u64) -> Option<FilteredReading> {
+        // Reject changes that are faster than the liquid could physically heat or cool.
+        // A run of rejected readings that agree with each other means the accepted value was wrong (a bad first
+        // reading) or the probe really moved (into a different vessel), so the filter restarts from them.
+        if let Some(last) = self.last_accepted {
+            if !self.plausible(last, raw, now_ms) {
+                self.spikes_rejected += 1;
+                self.rejected_run = match self.last_rejected {
+                    Some(rejected) if self.plausible(rejected, raw, now_ms) => self.rejected_run + 1,
+                    _ => 1,
+                };
+                self.last_rejected = Some((raw, now_ms));
+                if self.rejected_run < self.config.reseed_after {
+                    return None;
+                }
+                self.reseed();
+            }
+        }
+        self.last_accepted = Some((raw, now_ms));
+        self.last_rejected = None;
+        self.rejected_run = 0;
+
+        self.window.write(raw);
+        let median = self.median();
+        let filtered = match self.ema {
+            Some(ema) => ema + self.config.ema_alpha * (median - ema),
+            None => median,
+        };
+        self.ema = Some(filtered);
+
+        Some(FilteredReading { raw, filtered })
+    }
+
+    /// Whether `raw` could have followed `previous` (value, time in ms) given the rate and step limits
+    fn plausible(&self, previous: (f32, u64), raw: f32, now_ms: u64) -> bool {
+        let (value, at_ms) = previous;
+        let elapsed = now_ms.saturating_sub(at_ms) as f32 / 1000.0;
+        (raw - value).abs() <= (self.config.max_rate * elapsed).min(self.config.max_step)
+    }
+
+    /// Forget the accepted readings so the next one starts the median and moving average afresh
+    fn reseed(&mut self) {
+        self.window.clear();
+        self.ema = None;
+        self.last_accepted = None;
+    }
+
+    /// Count a read that failed its CRC check. The reading itself is never used.
+    pub fn record_crc_failure(&mut self) {
+        self.crc_failures += 1;
+    }
+
+    /// Number of reads that failed their CRC check
+    pub fn crc_failures(&self) -> u32 {
+        self.crc_failures
+    }
+
+    /// Number of readings rejected for changing faster than `max_rate`
+    pub fn spikes_rejected(&self) -> u32 {
+        self.spikes_rejected
+    }
+
+    /// Median of the readings currently in the window
+    fn median(&self) -> f32 {
+        let mut sorted = [0.0f32; N];
+        let len = self.window.len();
+        for (slot, value) in sorted.iter_mut().zip(self.window.iter()) {
+            *slot = *value;
+        }
+        let sorted = &mut sorted[..len];
+        sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+        if len.is_multiple_of(2) {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+        } else {
+            sorted[len / 2]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: FilterConfig = FilterConfig { ema_alpha: 0.5, max_rate: 0.1, max_step: 2.0, reseed_after: 3 };
+    const UNSMOOTHED: FilterConfig = FilterConfig { ema_alpha: 1.0, max_rate: 10.0, ..CONFIG };
+
+    #[test]
+    fn single_spike_is_rejected() {
+        let mut filter: ReadingFilter<1> = ReadingFilter::new(CONFIG);
+        assert!(filter.push(20.0, 0).is_some());
+        assert!(filter.push(30.0, 10_000).is_none());     // 10 degrees in 10 s
+        let reading = filter.push(20.5, 20_000).unwrap();     // Checked against the last accepted reading, not the spike
+        assert_eq!(reading.raw, 20.5);
+        assert_eq!(filter.spikes_rejected(), 1);
+    }
+
+    #[test]
+    fn step_is_limited_however_long_the_gap() {
+        let mut filter: ReadingFilter<1> = ReadingFilter::new(CONFIG);
+        filter.push(20.0, 0);
+        assert!(filter.push(25.0, 3_600_000).is_none());    // The rate allows it after an hour, the step doesn't
+    }
+
+    #[test]
+    fn real_step_is_accepted_after_agreeing_rejections() {
+        let mut filter: ReadingFilter<3> = ReadingFilter::new(CONFIG);
+        filter.push(20.0, 0);
+        assert!(filter.push(30.0, 10_000).is_none());
+        assert!(filter.push(30.0, 20_000).is_none());
+        // The third agreeing reading restarts the filter, so the old readings don't drag the result down
+        let reading = filter.push(30.0, 30_000).unwrap();
+        assert_eq!(reading.filtered, 30.0);
+        assert_eq!(filter.spikes_rejected(), 3);
+        assert!(filter.push(30.5, 40_000).is_some());   // Now checked against the new value
+    }
+
+    #[test]
+    fn disagreeing_rejections_do_not_reseed() {
+        let mut filter: ReadingFilter<1> = ReadingFilter::new(CONFIG);
+        filter.push(20.0, 0);
+        assert!(filter.push(30.0, 10_000).is_none());
+        assert!(filter.push(40.0, 20_000).is_none());
+        assert!(filter.push(30.0, 30_000).is_none());
+        assert_eq!(filter.spikes_rejected(), 3);
+    }
+
+    #[test]
+    fn median_of_the_window() {
+        let mut filter: ReadingFilter<3> = ReadingFilter::new(UNSMOOTHED);
+        filter.push(20.0, 0);
+        assert_eq!(filter.push(21.0, 1_000).unwrap().filtered, 20.5);  // Even count, the middle two are averaged
+        assert_eq!(filter.push(20.25, 2_000).unwrap().filtered, 20.25);
+        assert_eq!(filter.push(20.0, 3_000).unwrap().filtered, 20.25); // The first reading has left the window
+    }
+
+    #[test]
+    fn moving_average_follows_the_median() {
+        let mut filter: ReadingFilter<1> = ReadingFilter::new(CONFIG);
+        assert_eq!(filter.push(20.0, 0).unwrap().filtered, 20.0);   // The first reading seeds the average
+        assert_eq!(filter.push(21.0, 10_000).unwrap().filtered, 20.5);
+        assert_eq!(filter.push(21.0, 20_000).unwrap().filtered, 20.75);
+    }
+
+    #[test]
+    fn counters_start_at_zero_and_count_up() {
+        let mut filter: ReadingFilter<1> = ReadingFilter::new(CONFIG);
+        assert_eq!((filter.crc_failures(), filter.spikes_rejected()), (0, 0));
+        filter.record_crc_failure();
+        filter.record_crc_failure();
+        filter.push(20.0, 0);
+        filter.push(25.0, 1_000);
+        assert_eq!((filter.crc_failures(), filter.spikes_rejected()), (2, 1));
+    }
+}
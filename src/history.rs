@@ -0,0 +1,74 @@
+/// One point on the temperature history graph
+#[derive(Copy, Clone, Default, PartialEq, defmt::Format)]
+pub struct HistorySample {
+    pub temp: f32,
+    pub target: f32,
+    pub heating: bool,      // The heating relay was on at some point during the interval
+    pub cooling: bool,      // The cooling relay was on at some point during the interval
+}
+
+/// The last `N` samples, oldest overwritten first
+pub struct History<const N: usize> {
+    samples: [HistorySample; N],
+    next: usize,    // Where the next sample goes
+    len: usize,
+}
+
+impl<const N: usize> History<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: [HistorySample { temp: 0.0, target: 0.0, heating: false, cooling: false }; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: HistorySample) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The samples, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &HistorySample> {
+        let start = (self.next + N - self.len) % N;
+        (0..self.len).map(move |i| &self.samples[(start + i) % N])
+    }
+
+    /// The most recent `count` samples, oldest first
+    pub fn latest(&self, count: usize) -> impl Iterator<Item = &HistorySample> {
+        self.iter().skip(self.len.saturating_sub(count))
+    }
+
+    /// Lowest and highest of the temperatures and targets in the most recent `count` samples,
+    /// widened to at least `min_span` so a flat trace doesn't fill the graph with noise
+    pub fn range(&self, count: usize, min_span: f32) -> Option<(f32, f32)> {
+        let mut samples = self.latest(count);
+        let first = samples.next()?;
+        let (mut low, mut high) = (first.temp.min(first.target), first.temp.max(first.target));
+        for sample in samples {
+            low = low.min(sample.temp).min(sample.target);
+            high = high.max(sample.temp).max(sample.target);
+        }
+        if high - low < min_span {
+            let middle = (high + low) / 2.0;
+            low = middle - min_span / 2.0;
+            high = middle + min_span / 2.0;
+        }
+        Some((low, high))
+    }
+}
+
+impl<const N: usize> Default for History<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
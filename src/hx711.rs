@@ -0,0 +1,140 @@
+use embassy_time::{block_for, with_timeout, Duration};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+
+use crate::sensor::SensorError;
+
+const CO2_TO_EXTRACT: f32 = 2.0665 / 0.9565;    // Grams of extract fermented for every gram of CO2 given off (Balling)
+const EXTRACT_PER_POINT: f32 = 2.6;             // Grams of extract per litre for each gravity point (0.001 SG)
+
+/// Input channel and gain the HX711 is set to for the next conversion
+#[derive(Copy, Clone, PartialEq)]
+pub enum Gain {
+    A128,   // Channel A, gain 128
+    A64,    // Channel A, gain 64
+    B32,    // Channel B, gain 32
+}
+
+impl Gain {
+    /// Number of extra clock pulses after the 24 data bits that select this gain
+    fn pulses(&self) -> u8 {
+        match self {
+            Gain::A128 => 1,
+            Gain::A64 => 3,
+            Gain::B32 => 2,
+        }
+    }
+}
+
+/// Calibration for a load cell on an HX711
+#[derive(Copy, Clone)]
+pub struct ScaleConfig {
+    pub gain: Gain,
+    pub offset: i32,            // Reading with nothing on the scale (tare)
+    pub counts_per_gram: f32,   // Change in reading for each gram on the scale
+    pub samples: u8,            // Number of readings averaged for each weight
+}
+
+/// HX711 24-bit load cell ADC, bit-banged on two GPIO pins
+pub struct Hx711<DOUT, SCK> {
+    dout: DOUT,
+    sck: SCK,
+    config: ScaleConfig,
+}
+
+impl<DOUT: InputPin + Wait, SCK: OutputPin> Hx711<DOUT, SCK> {
+    pub fn new(dout: DOUT, mut sck: SCK, config: ScaleConfig) -> Self {
+        let _ = sck.set_low();  // Holding SCK high for more than 60us powers the HX711 down
+        Self { dout, sck, config }
+    }
+
+    /// Read one raw, signed 24-bit conversion
+    pub async fn read_raw(&mut self) -> Result<i32, SensorError> {
+        // DOUT goes low when a conversion is ready, which is at most 100ms at 10 samples per second
+        with_timeout(Duration::from_millis(200), self.dout.wait_for_low())
+            .await
+            .map_err(|_| SensorError::NoPresence)?
+            .map_err(|_| SensorError::BusError)?;
+
+        let mut value: u32 = 0;
+        // The clock must not be held high for long, so don't let an interrupt stretch a pulse
+        critical_section::with(|_| {
+            for _ in 0..24 {
+                let _ = self.sck.set_high();
+                block_for(Duration::from_micros(1));
+                value = (value << 1) | self.dout.is_high().unwrap_or(false) as u32;
+                let _ = self.sck.set_low();
+                block_for(Duration::from_micros(1));
+            }
+            for _ in 0..self.config.gain.pulses() {
+                let _ = self.sck.set_high();
+                block_for(Duration::from_micros(1));
+                let _ = self.sck.set_low();
+                block_for(Duration::from_micros(1));
+            }
+        });
+
+        // A disconnected DOUT reads as all ones, a shorted one never goes high
+        match value {
+            0xFF_FFFF => return Err(SensorError::OpenCircuit),
+            0x00_0000 => return Err(SensorError::ShortCircuit),
+            _ => {}
+        }
+        Ok(((value << 8) as i32) >> 8)     // Sign extend from 24 bits
+    }
+
+    /// Average of `config.samples` raw readings
+    pub async fn read_average(&mut self) -> Result<i32, SensorError> {
+        let samples = self.config.samples.max(1);
+        let mut total: i64 = 0;
+        for _ in 0..samples {
+            total += self.read_raw().await? as i64;
+        }
+        Ok((total / samples as i64) as i32)
+    }
+
+    /// Weight on the scale in grams
+    pub async fn weight(&mut self) -> Result<f32, SensorError> {
+        let raw = self.read_average().await?;
+        Ok((raw - self.config.offset) as f32 / self.config.counts_per_gram)
+    }
+
+    /// Zero the scale with whatever is on it now
+    pub async fn tare(&mut self) -> Result<(), SensorError> {
+        self.config.offset = self.read_average().await?;
+        Ok(())
+    }
+
+    /// Set the scale factor from a known mass (grams) placed on the tared scale
+    pub async fn calibrate(&mut self, known_grams: f32) -> Result<(), SensorError> {
+        let raw = self.read_average().await?;
+        let counts = (raw - self.config.offset) as f32;
+        if known_grams <= 0.0 || counts == 0.0 {
+            return Err(SensorError::ConfigMismatch);
+        }
+        self.config.counts_per_gram = counts / known_grams;
+        Ok(())
+    }
+
+    /// The current calibration, to be copied into the configuration once the scale has been calibrated
+    pub fn config(&self) -> ScaleConfig {
+        self.config
+    }
+}
+
+/// Fermentation progress worked out from the weight the fermenter has lost as CO2
+#[derive(Copy, Clone)]
+pub struct FermentProgress {
+    pub co2_loss: f32,      // Grams of CO2 given off since the start weight was taken
+    pub gravity_drop: f32,  // Estimated fall in specific gravity (e.g. 0.040)
+}
+
+impl FermentProgress {
+    /// Progress of `volume` litres of wort that weighed `start` grams and now weighs `current` grams
+    pub fn new(start: f32, current: f32, volume: f32) -> Self {
+        let co2_loss = (start - current).max(0.0);
+        let extract = co2_loss * CO2_TO_EXTRACT;
+        let gravity_drop = extract / volume / EXTRACT_PER_POINT / 1000.0;
+        Self { co2_loss, gravity_drop }
+    }
+}
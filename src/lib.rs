@@ -1,12 +1,38 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 pub mod adjustment;
+#[cfg(feature = "rp2040")]
+pub mod analog;
 pub mod controls;
 pub mod display;
+pub mod ds2482;
+#[cfg(feature = "rp2040")]
+pub mod enclosure;
+pub mod filter;
+pub mod history;
+pub mod hx711;
+pub mod main_menu;
+pub mod max31865;
+pub mod menu;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "rp2040")]
+pub mod ntc;
+pub mod oled;
+pub mod onewire;
+pub mod panel;
+#[cfg(feature = "rp2040")]
+pub mod pio_onewire;
+#[cfg(feature = "rp2040")]
+pub mod pressure;
+pub mod profile;
 pub mod sensor;
-pub mod sh1107;
-//pub mod sh1107new;
+pub mod settings;
+pub mod sht;
+pub mod switch;
+pub mod temperature;
+pub mod thermocouple;
 
 //pub const MIN_SUPPORTED_TEMP: f32 = 11.0; // Minimum selectable temperature
 //pub const MAX_SUPPORTED_TEMP: f32 = 27.0; // Maximum selectable temperature
@@ -20,5 +46,6 @@ pub mod sh1107;
 //pub const TERM_KD: f32 = 150.0; //
 
 pub enum AutoBrewError {
-    SensorNotFoundError,
+    SensorFaultError(sensor::SensorError),
+    ReadingRejectedError,
 }
\ No newline at end of file
@@ -1,48 +1,176 @@
 #![no_std]
 #![no_main]
 use core::fmt::Write;
-use defmt::{error, info};
-use heapless::String;
+use defmt::{error, info, warn};
+use heapless::{String, Vec};
+use embedded_hal::digital::OutputPin;
 use embedded_hal_async::delay::DelayNs;
 use embassy_executor::Spawner;
-use embassy_rp::bind_interrupts;
+use embassy_rp::{adc, bind_interrupts, i2c, spi};
+use embassy_rp::adc::Adc;
 use embassy_rp::gpio::{Level, Output, Input, Pull};
-use embassy_rp::peripherals::{PIO0, FLASH};
+use embassy_rp::i2c::I2c;
+use embassy_rp::peripherals::{PIO0, FLASH, I2C0, SPI0};
 use embassy_rp::pio::{InterruptHandler, Pio};
-use embassy_rp::pio_programs::onewire::{PioOneWire, PioOneWireProgram};
+use embassy_rp::spi::Spi;
 use embassy_time::{Delay, Instant, Timer};
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use embassy_rp::flash::{Async, Flash, ERASE_SIZE};
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDeviceWithConfig;
+use static_cell::StaticCell;
 use {defmt_rtt as _, panic_probe as _};
-use auto_brew_rs::{display::*, sensor::*, AutoBrewError};
+use auto_brew_rs::ds2482::{self, Ds2482};
+use auto_brew_rs::onewire::{OneWire, Triplet};
+use auto_brew_rs::history::{History, HistorySample};
+use auto_brew_rs::main_menu::*;
+use auto_brew_rs::menu::{Entry, EntryKind, Key, Menu, MenuEvent, Value};
+use auto_brew_rs::oled::Rotation;
+use auto_brew_rs::panel::Sh1107x64;
+use auto_brew_rs::pio_onewire::{PioOneWire, PioOneWireProgram};
+use auto_brew_rs::settings::{Loaded, ScaleCalibration, Settings, SETTINGS_SIZE, SETTINGS_VERSION};
+use auto_brew_rs::{analog::*, display::*, enclosure::*, filter::*, hx711::*, max31865::*, ntc::*, pressure::*, profile::*, sensor::*, sht::*, switch::*, temperature::*, thermocouple::*, AutoBrewError};
 
 // static variables
 static NO_DEVICE: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);              // Indicates if no temperature sensor was detected
+static SENSOR_FAULT: Mutex<ThreadModeRawMutex, Option<SensorError>> = Mutex::new(None);  // The fault seen on the last failed sensor read
+static PROBE_ERRORS: Mutex<ThreadModeRawMutex, ErrorCounters> = Mutex::new(ErrorCounters::new());    // Count of each kind of fault seen on the main probe
 static LAST_UPDATE: Mutex<ThreadModeRawMutex, u64> = Mutex::new(0);                 // The last time the temp was checked
 static LAST_DISPLAY: Mutex<ThreadModeRawMutex, u64> = Mutex::new(0);                // The last time the display was updated
-static CURRENT_TEMP: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);              // The current temperature reading
+static CURRENT_TEMP: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);              // The current (filtered) temperature reading
+static RAW_TEMP: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);                  // The last unfiltered temperature reading
+static TEMP_ALARM: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);             // Indicates the sensor has flagged a TH/TL hardware alarm
+static SENSOR_REMOVED: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);         // Indicates a sensor that was seen before has been unplugged
+static OUTPUT_STATES: Mutex<ThreadModeRawMutex, RoleStates> = Mutex::new(RoleStates::new());    // The state wanted for each 1-Wire switch output role
+static OUTPUTS_CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();            // Asks the sensor task to update the 1-Wire switch outputs
+static OUTPUT_FAULT: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);           // Indicates a 1-Wire switch did not read back the state written to it
+static BUS_TEMPS: Mutex<ThreadModeRawMutex, [Option<f32>; EXTRA_BUS_COUNT]> = Mutex::new([None; EXTRA_BUS_COUNT]);     // The last reading of the probe on each extra 1-Wire bus
+static BUS_SENSOR_REMOVED: Mutex<ThreadModeRawMutex, [bool; EXTRA_BUS_COUNT]> = Mutex::new([false; EXTRA_BUS_COUNT]);  // Indicates a sensor on an extra 1-Wire bus has been unplugged
+static BUS_ERRORS: Mutex<ThreadModeRawMutex, [ErrorCounters; EXTRA_BUS_COUNT]> = Mutex::new([ErrorCounters::new(); EXTRA_BUS_COUNT]);  // Count of each kind of fault seen on each extra bus's probe
 static TARGET_TEMP: Mutex<ThreadModeRawMutex, f32> = Mutex::new(19.0);              // Target temperature to maintain (Default = 19 degrees C)
 static CURRENT_VARIANCE: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);          // The current variance
 static LAST_VARIANCE: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);             // The last calculated variance
 static PIN_INTERRUPT: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);          // Indicates that there was an interrupt from a GPIO pin
 static DISPLAY_KEY0_PRESSED: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);   // Indicates if button (key0) was pressed
 static DISPLAY_KEY1_PRESSED: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);   // Indicates if button (key1) was pressed
+static DISPLAY_KEY0_HELD: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);      // Indicates if button (key0) was held for `LONG_PRESS_MS`
+static DISPLAY_KEY1_HELD: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);      // Indicates if button (key1) was held for `LONG_PRESS_MS`
 static DISPLAY_ON: Mutex<ThreadModeRawMutex, bool> = Mutex::new(true);              // Indicates that the display is on
 static RELAY_ON: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);               // Indicates that a relay is on
 static SWITCH_OFF_RELAYS: Mutex<ThreadModeRawMutex, u64> = Mutex::new(0);           // The time that the relays should be switched off at
 static INTEGRAL: Mutex<ThreadModeRawMutex, f32> = Mutex::new(0.0);                  // The calculated integral value
+static TEMP_REQUEST: Signal<ThreadModeRawMutex, ()> = Signal::new();                // Asks the sensor task for a new temperature reading
+static TEMP_READY: Signal<ThreadModeRawMutex, ()> = Signal::new();                  // Signalled by the sensor task when a requested reading is finished
+static ADC: StaticCell<SharedAdc<'static>> = StaticCell::new();                     // The ADC, shared by the analog inputs
+static I2C0_BUS: StaticCell<I2c0Bus> = StaticCell::new();                           // I2C0, shared by the humidity sensor, the DS2482 bridge and an I2C display
+static SPI0_BUS: StaticCell<Spi0Bus> = StaticCell::new();                           // SPI0, shared by the RTD converter and the thermocouple
+static KETTLE_TEMP: Mutex<ThreadModeRawMutex, Option<f32>> = Mutex::new(None);      // The last boil kettle / HLT thermocouple reading
+static KETTLE_FAULT: Mutex<ThreadModeRawMutex, Option<SensorError>> = Mutex::new(None);  // The fault seen on the last failed thermocouple read
+static CHAMBER: Mutex<ThreadModeRawMutex, Option<HumidityReading>> = Mutex::new(None);  // The last chamber humidity reading
+static HUMIDITY_ALARM: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);         // Indicates the chamber humidity is above `HUMIDITY_LIMIT`
+static PRESSURE: Mutex<ThreadModeRawMutex, Option<f32>> = Mutex::new(None);         // The last fermenter pressure reading (bar)
+static SPUNDING_TARGET: Mutex<ThreadModeRawMutex, Option<f32>> = Mutex::new(None);  // Pressure the spunding valve holds (bar), None to only vent at the limit
+static OVER_PRESSURE: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);          // Indicates the pressure has reached `PRESSURE_LIMIT` or is above the transducer's range
+static PRESSURE_FAULT: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);         // Indicates the pressure can't be read, so the valve is held open
+static FERMENT_PROGRESS: Mutex<ThreadModeRawMutex, Option<FermentProgress>> = Mutex::new(None);  // CO2 loss and gravity drop from the fermenter scale
+static START_WEIGHT: Mutex<ThreadModeRawMutex, Option<f32>> = Mutex::new(None);     // Fermenter weight at pitching (grams), kept in flash so CO2 loss survives a restart
+static SCALE_CALIBRATION: Mutex<ThreadModeRawMutex, Option<ScaleCalibration>> = Mutex::new(None);  // Tare and scale factor set from the menu, kept in flash
+static SCALE_COMMAND: Signal<ThreadModeRawMutex, ScaleCommand> = Signal::new();     // Asks the scale task to tare, calibrate or take the start weight
+static SETTINGS_CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();            // Asks the main loop to save the settings after another task changed them
+static ENCLOSURE_TEMP: Mutex<ThreadModeRawMutex, Option<f32>> = Mutex::new(None);   // The last reading of the RP2040's on-die temperature sensor
+static ENCLOSURE_ALARM: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);        // Indicates the enclosure is over `ENCLOSURE_LIMIT`
+static HISTORY: Mutex<ThreadModeRawMutex, History<GRAPH_POINTS>> = Mutex::new(History::new());  // Temperature, target and relay history for the graph screen
+static SHOW_GRAPH: Mutex<ThreadModeRawMutex, bool> = Mutex::new(false);             // Indicates the history graph is shown instead of the readings
+static TEMP_UNIT: Mutex<ThreadModeRawMutex, TemperatureUnit> = Mutex::new(DEFAULT_TEMP_UNIT);  // Unit for the setpoint step, the display and the serial output
 
 // constants
-const MIN_TEMP: f32 = 11.0;                 // Minimum selectable temp
-const MAX_TEMP: f32 = 27.0;                 // Maximum selectable temp
+const DEFAULT_TEMP_UNIT: TemperatureUnit = TemperatureUnit::Celsius;    // Unit until one is chosen in the menu, also given to settings saved before the unit could be
+const MIN_TEMP: f32 = 11.0;                 // Minimum selectable temp (degrees C, rounded to whole steps of the unit in use)
+const MAX_TEMP: f32 = 27.0;                 // Maximum selectable temp (degrees C, rounded to whole steps of the unit in use)
 const CHECK_IN: i16 = 300;                  // Temperature check interval (seconds)
 const NO_DEVICE_CHECK_IN: i8 = 60;          // Check interval for when no temperature sensor was detected previously (seconds)
-const DISPLAY_TIMEOUT: i8 = 30;             // Turn off display to avoid burn-in
+const IDLE_POLICY: IdlePolicy = IdlePolicy {    // Dim, then show a clock, then sleep when no key has been pressed to avoid burn-in
+    dim_after: 30,          // Seconds
+    dim_contrast: 0x08,
+    clock_after: 300,       // Seconds
+    sleep_after: None,      // Seconds, None to keep showing the clock
+    max_shift: 2,           // Pixels the screens are moved around by
+};
 const TOLERANCE: f32 = 0.25;                // Allowable variance on either side of the target
 const KP: f32 = 10.0;                       // Proportional term - Basic steering (This is the first parameter you should tune for a particular setup)
 const KI: f32 = 0.01;                       // Integral term - Compensate for heat loss by vessel
 const KD: f32 = 150.0;                      // Derivative term - Compensate for overshoot (This is the last parameter you should tune for a particular setup)
+const FILTER_MEDIAN_SIZE: usize = 3;        // Number of readings the median filter is taken over
+const FILTER_EMA_ALPHA: f32 = 0.5;          // Weight given to each new reading by the moving average (1.0 = no smoothing)
+const FILTER_MAX_RATE: f32 = 0.1;           // Largest believable change in temperature (degrees C per second)
+const FILTER_MAX_STEP: f32 = 2.0;           // Largest believable change between two readings, however long apart (degrees C)
+const FILTER_RESEED_AFTER: u32 = 3;         // Agreeing rejected readings in a row before the filter restarts from them
+const SENSOR_STRONG_PULLUP: bool = false;   // Set if a strong pull-up transistor for parasite powered sensors is fitted (driven by GPIO18)
+const LONG_PRESS_MS: u64 = 1000;            // Holding a key this long is a long press (key0 opens the menu, key1 switches to the history graph)
+const HISTORY_HOURS: u64 = 12;              // Time span of the history graph (hours)
+const GRAPH_POINTS: usize = 128;            // Samples kept for the history graph, one per pixel across the panel
+const HISTORY_INTERVAL: u64 = HISTORY_HOURS * 3600 / GRAPH_POINTS as u64;  // Time between history samples (seconds)
+const DIAGNOSTICS_TIME: u64 = 3;            // How long the diagnostics screen is shown after the splash screen (seconds)
+const ALARM_HIGH: i8 = 30;                  // Hardware over-temperature limit programmed into the sensor (TH)
+const ALARM_LOW: i8 = 5;                    // Hardware under-temperature limit programmed into the sensor (TL)
+const SENSOR_CONFIG: SensorConfig = SensorConfig {  // Configuration written to every DS18B20 found on the bus
+    alarm_high: ALARM_HIGH,
+    alarm_low: ALARM_LOW,
+    resolution: Resolution::Bits12,     // 12 bits (0.0625 degrees C)
+};
+const CALIBRATION: &[([u8; 8], f32)] = &[]; // Calibration offsets (degrees C) for known DS18B20 ROM codes
+const ACTUATORS: &[RoleAssignment] = &[];   // Channels of DS2413/DS2408 switches on the sensor bus and the roles they follow
+const HOTPLUG_CHECK_IN: u64 = 10;           // Interval between searches of the 1-Wire bus for added or removed sensors (seconds)
+const EXTRA_BUS_COUNT: usize = 3;           // PIO0 state machines left over for extra 1-Wire buses (state machine 0 drives the main probe)
+const EXTRA_BUSES: [Option<ExtraBus>; EXTRA_BUS_COUNT] = [None, None, None];  // Extra 1-Wire buses on state machines 1-3 (GPIO13, GPIO19, GPIO22), e.g. Some(ExtraBus { name: "Air", check_in: 60 })
+const SENSOR_RETRY: RetryPolicy = RetryPolicy { attempts: 3, backoff_ms: 100 };    // Retries for a failed sensor read
+const DISPLAY_BUS: DisplayBus = DisplayBus::Spi;   // How the OLED module is wired
+type DisplayPanel = Sh1107x64;              // OLED controller and size (Sh1107x64, Sh1107x128, Ssd1306 or Sh1106)
+const DISPLAY_ROTATION: Rotation = Rotation::Deg0;  // Clockwise rotation for how the panel is mounted in the enclosure (90 and 270 need a square panel)
+const _: () = assert!(MainDisplay::supports_rotation(DISPLAY_ROTATION), "DISPLAY_ROTATION needs a square panel");
+const DISPLAY_MIRROR: bool = false;         // Flip the picture left to right (before rotating)
+const HUMIDITY_MODEL: Option<ShtModel> = Some(ShtModel::Sht3x);   // Chamber humidity sensor on I2C0 (GPIO20 = SDA, GPIO21 = SCL), None if not fitted
+const HUMIDITY_CHECK_IN: u64 = 60;          // Humidity check interval (seconds)
+const HUMIDITY_LIMIT: f32 = 80.0;           // Relative humidity (%) above which condensation and mould become a risk
+const PRESSURE_UNIT: PressureUnit = PressureUnit::Psi;  // Unit for the pressure settings below and the display
+const SPUNDING_PRESSURE: Option<f32> = None;    // Pressure to hold when no profile step sets one, None to keep the valve shut
+const PRESSURE_LIMIT: f32 = 30.0;           // Hard over-pressure limit, always vented
+const PRESSURE_HYSTERESIS: f32 = 0.5;       // The valve closes this far below the target
+const PRESSURE_CHECK_IN_MS: u64 = 1000;     // Pressure check interval (milliseconds)
+const STATUS_PAGE_SECS: u64 = 4;            // How long each reading is shown on the status line before the next one
+const TRANSDUCER_CONFIG: Option<TransducerConfig> = Some(TransducerConfig {   // 0 - 60 psi transducer on GPIO27 (ADC1) with a 10k/20k divider, None if not fitted
+    zero_voltage: 0.5,
+    full_voltage: 4.5,
+    full_scale: 4.137,
+    divider_ratio: 2.0 / 3.0,
+    samples: 16,
+});
+const ENCLOSURE_LIMIT: f32 = 55.0;          // Enclosure temperature that puts the controller in its fail-safe state (degrees C)
+const ENCLOSURE_HYSTERESIS: f32 = 5.0;      // The enclosure alarm clears this far below the limit
+const ENCLOSURE_CHECK_IN: u64 = 10;         // Enclosure temperature check interval (seconds)
+const SCALE_CONFIG: Option<ScaleConfig> = None;    // HX711 load cell under the fermenter (GPIO0 = DOUT, GPIO1 = SCK), None if not fitted
+const WORT_VOLUME: f32 = 20.0;              // Volume of wort in the fermenter (litres), used to estimate the gravity drop
+const SCALE_CHECK_IN: u64 = 60;             // Fermenter weight check interval (seconds)
+const THERMOCOUPLE: Option<ThermocoupleType> = None;    // Boil kettle / HLT thermocouple on SPI0 (GPIO2 = SCK, GPIO4 = MISO, GPIO28 = CS), None if not fitted
+const KETTLE_CHECK_IN: u64 = 5;             // Thermocouple check interval (seconds)
+const PROFILE: &[ProfileStep] = &[];        // Fermentation profile, leave empty to hold the target temperature set with the buttons
+const PROBE_TYPE: ProbeType = ProbeType::Ds18b20;   // The temperature probe fitted to the controller
+const BRIDGE_CHANNEL: Option<u8> = None;            // DS2482-800 channel the sensors are on (0 - 7), None for a DS2482-100
+const NTC_CONFIG: NtcConfig = NtcConfig {           // 10k NTC thermowell on GPIO26 (ADC0)
+    series_resistor: 10_000.0,
+    coefficients: SteinhartHart::NTC_10K,
+    samples: 16,
+};
+const RTD_CONFIG: Max31865Config = Max31865Config { // MAX31865 on SPI0 (GPIO2 = SCK, GPIO3 = MOSI, GPIO4 = MISO, GPIO5 = CS)
+    rtd: RtdType::Pt100,
+    wires: RtdWires::Three,
+    reference_resistor: 430.0,
+    filter_50hz: true,
+    fault_low: -50.0,       // Degrees C
+    fault_high: 150.0,      // Degrees C
+};
 
 const FLASH_SIZE: usize = 2 * 1024 * 1024;  // 2MB flash
 const ADDR_OFFSET: u32 = 0x100000;  // Start at 1MB offset
@@ -56,28 +184,602 @@ const ADDR_OFFSET: u32 = 0x100000;  // Start at 1MB offset
 
 bind_interrupts!(struct Irqs {
     PIO0_IRQ_0 => InterruptHandler<PIO0>;
+    ADC_IRQ_FIFO => adc::InterruptHandler;
+    I2C0_IRQ => i2c::InterruptHandler<I2C0>;
 });
 
-// Get the current temperature from the sensor and update the global variables
-async fn get_current_temp(temp_sensor: &mut Ds18b20<'_, PIO0, 0>) -> Result<f32, AutoBrewError> {
-    temp_sensor.start().await;      // Start a new measurement
-    Timer::after_secs(1).await;     // Allow 1s for the measurement to finish
-    match temp_sensor.temperature().await {
+/// Buses the OLED module can be wired to
+#[allow(dead_code)]
+#[derive(PartialEq)]
+enum DisplayBus {
+    Spi,        // SPI1 (GPIO8 = DC, GPIO9 = CS, GPIO10 = SCK, GPIO11 = MOSI, GPIO12 = RST)
+    I2c,        // I2C0 at `DEFAULT_I2C_ADDRESS`, shared with the humidity sensor (GPIO20 = SDA, GPIO21 = SCL, GPIO12 = RST)
+}
+
+/// Temperature probes the controller can be built with (only the one in `PROBE_TYPE` is constructed)
+#[allow(dead_code)]
+enum ProbeType {
+    Ds18b20,    // DS18B20 on the 1-Wire bus
+    Ds2482,     // DS18B20 behind a DS2482 I2C to 1-Wire bridge, for boards where GPIO16 and the PIO are used elsewhere
+    Ntc,        // NTC thermistor on the ADC
+    Rtd,        // PT100/PT1000 through a MAX31865
+}
+
+/// An extra 1-Wire bus with its own probe, so a short or noise on its cable can't take down the main probe
+#[derive(Copy, Clone)]
+struct ExtraBus {
+    name: &'static str,     // Shown on the status line (up to 5 characters)
+    check_in: u64,          // Temperature check interval (seconds)
+}
+
+/// The state machine driving an extra 1-Wire bus, so one task function can run every bus
+enum ExtraWire {
+    Sm1(PioOneWire<'static, PIO0, 1>),
+    Sm2(PioOneWire<'static, PIO0, 2>),
+    Sm3(PioOneWire<'static, PIO0, 3>),
+}
+
+impl OneWire for ExtraWire {
+    async fn reset(&mut self) -> Result<bool, SensorError> {
+        match self {
+            ExtraWire::Sm1(wire) => wire.reset().await,
+            ExtraWire::Sm2(wire) => wire.reset().await,
+            ExtraWire::Sm3(wire) => wire.reset().await,
+        }
+    }
+
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SensorError> {
+        match self {
+            ExtraWire::Sm1(wire) => wire.write_bytes(bytes).await,
+            ExtraWire::Sm2(wire) => wire.write_bytes(bytes).await,
+            ExtraWire::Sm3(wire) => wire.write_bytes(bytes).await,
+        }
+    }
+
+    async fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SensorError> {
+        match self {
+            ExtraWire::Sm1(wire) => wire.read_bytes(buffer).await,
+            ExtraWire::Sm2(wire) => wire.read_bytes(buffer).await,
+            ExtraWire::Sm3(wire) => wire.read_bytes(buffer).await,
+        }
+    }
+
+    async fn triplet(&mut self, direction: bool) -> Result<Triplet, SensorError> {
+        match self {
+            ExtraWire::Sm1(wire) => wire.triplet(direction).await,
+            ExtraWire::Sm2(wire) => wire.triplet(direction).await,
+            ExtraWire::Sm3(wire) => wire.triplet(direction).await,
+        }
+    }
+}
+
+// The menu, with quarter turns offered only when the panel is square
+const QUARTER_TURNS: bool = MainDisplay::supports_rotation(Rotation::Deg90);
+const MENU: [Entry<Screen>; MENU_ENTRIES] = main_menu(QUARTER_TURNS, MIN_TEMP, MAX_TEMP, DEFAULT_TEMP_UNIT);
+const ROTATIONS: &[Rotation] = rotations(QUARTER_TURNS);
+
+type MainDisplay = BoardDisplay<'static, I2c0Device, DisplayPanel>;
+type I2c0Bus = Mutex<ThreadModeRawMutex, I2c<'static, I2C0, i2c::Async>>;
+type I2c0Device = I2cDevice<'static, ThreadModeRawMutex, I2c<'static, I2C0, i2c::Async>>;
+type Spi0Bus = Mutex<ThreadModeRawMutex, Spi<'static, SPI0, spi::Async>>;
+type Spi0Device = SpiDeviceWithConfig<'static, ThreadModeRawMutex, Spi<'static, SPI0, spi::Async>, Output<'static>>;
+
+/// The probe the control loop takes its readings from
+enum Probe {
+    Ds18b20(Ds18b20<PioOneWire<'static, PIO0, 0>, Output<'static>>),
+    Bridge(Ds18b20<Ds2482<I2c0Device>, Output<'static>>),
+    Ntc(NtcThermistor<'static>),
+    Rtd(Max31865<Spi0Device>),
+}
+
+impl TemperatureSensor for Probe {
+    async fn read_temperature(&mut self) -> Result<f32, SensorError> {
+        match self {
+            Probe::Ds18b20(sensor) => sensor.read_temperature().await,
+            Probe::Bridge(sensor) => sensor.read_temperature().await,
+            Probe::Ntc(sensor) => sensor.read_temperature().await,
+            Probe::Rtd(sensor) => sensor.read_temperature().await,
+        }
+    }
+}
+
+// Get the current temperature from the sensor, pass it through the filter and update the global variables.
+// Failed reads are retried according to `SENSOR_RETRY`.
+async fn get_current_temp(temp_sensor: &mut impl TemperatureSensor, filter: &mut ReadingFilter<FILTER_MEDIAN_SIZE>) -> Result<f32, AutoBrewError> {
+    let mut result = Err(SensorError::NoPresence);
+    for attempt in 0..SENSOR_RETRY.attempts {
+        if attempt > 0 {
+            Timer::after(SENSOR_RETRY.backoff(attempt - 1)).await;
+        }
+        result = temp_sensor.read_temperature().await;
+        match result {
+            Ok(_) => break,
+            Err(fault) => {
+                PROBE_ERRORS.lock().await.record(fault);
+                if fault == SensorError::CrcMismatch {
+                    filter.record_crc_failure();
+                }
+                warn!("Sensor read failed: {:?} (attempt {:?} of {:?})", fault, attempt + 1, SENSOR_RETRY.attempts);     // Debug console
+            }
+        }
+    }
+
+    match result {
         Ok(temp) => {
             *NO_DEVICE.lock().await = false;
-            *CURRENT_TEMP.lock().await = temp;
-            *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await;
-            info!("temp = {:?} deg C", temp);   // Debug colsole
-            Ok(temp)
+            *SENSOR_FAULT.lock().await = None;
+            *RAW_TEMP.lock().await = temp;
+            match filter.push(temp, Instant::now().as_millis()) {
+                Some(reading) => {
+                    *CURRENT_TEMP.lock().await = reading.filtered;
+                    *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await;
+                    let unit = *TEMP_UNIT.lock().await;
+                    info!("temp = {:?} deg {} (raw = {:?})", unit.from_celsius(reading.filtered), unit.symbol(), unit.from_celsius(reading.raw));   // Debug colsole
+                    Ok(reading.filtered)
+                },
+                None => {
+                    let unit = *TEMP_UNIT.lock().await;
+                    warn!("Rejected temperature spike: {:?} deg {} ({:?} rejected so far)", unit.from_celsius(temp), unit.symbol(), filter.spikes_rejected());     // Debug console
+                    Err(AutoBrewError::ReadingRejectedError)
+                }
+            }
         },
-        _ => {
+        Err(fault) => {
             *NO_DEVICE.lock().await = true;
-            error!("Sensor not found");     // Debug console
-            Err(AutoBrewError::SensorNotFoundError)
+            *SENSOR_FAULT.lock().await = Some(fault);
+            error!("Sensor fault: {:?} ({:?})", fault, *PROBE_ERRORS.lock().await);     // Debug console
+            Err(AutoBrewError::SensorFaultError(fault))
+        }
+    }
+}
+
+// The message describing the current sensor fault
+async fn sensor_fault_message() -> &'static str {
+    match *SENSOR_FAULT.lock().await {
+        Some(fault) => fault.description(),
+        None => SensorError::NoPresence.description(),
+    }
+}
+
+#[embassy_executor::task]
+async fn sensor_task(mut probe: Probe, mut temp_filter: ReadingFilter<FILTER_MEDIAN_SIZE>) {
+    let mut roster = SensorRoster::new();
+    let mut actuators = Actuators::new(ACTUATORS);
+    probe.scan_for_sensors(&mut roster).await;
+    actuators.discover(roster.present());
+    probe.apply_outputs(&actuators).await;
+    loop {
+        // Take a reading when asked, switch the 1-Wire outputs when they change, otherwise check the bus for devices being plugged in or unplugged
+        match embassy_futures::select::select3(TEMP_REQUEST.wait(), OUTPUTS_CHANGED.wait(), Timer::after_secs(HOTPLUG_CHECK_IN)).await {
+            embassy_futures::select::Either3::First(_) => {
+                let _ = get_current_temp(&mut probe, &mut temp_filter).await;    // Get a temperature reading
+                match &mut probe {
+                    Probe::Ds18b20(temp_sensor) => check_alarms(temp_sensor).await,
+                    Probe::Bridge(temp_sensor) => check_alarms(temp_sensor).await,
+                    _ => {}
+                }
+                TEMP_READY.signal(());
+            }
+            embassy_futures::select::Either3::Second(_) => probe.apply_outputs(&actuators).await,
+            embassy_futures::select::Either3::Third(_) => {
+                probe.scan_for_sensors(&mut roster).await;
+                actuators.discover(roster.present());
+                probe.apply_outputs(&actuators).await;  // Bring a switch that has just been plugged in up to date
+            }
+        }
+    }
+}
+
+impl Probe {
+    // Only 1-Wire probes can be searched for
+    async fn scan_for_sensors(&mut self, roster: &mut SensorRoster) {
+        match self {
+            Probe::Ds18b20(temp_sensor) => *SENSOR_REMOVED.lock().await = scan_for_sensors(temp_sensor, roster).await,
+            Probe::Bridge(temp_sensor) => *SENSOR_REMOVED.lock().await = scan_for_sensors(temp_sensor, roster).await,
+            _ => {}
+        }
+    }
+
+    // Switch the outputs of the 1-Wire switches on the probe's bus, checking each one reads back the new state
+    async fn apply_outputs(&mut self, actuators: &Actuators<'_>) {
+        if actuators.switches().is_empty() {
+            return;
         }
+        let states = *OUTPUT_STATES.lock().await;
+        let result = match self {
+            Probe::Ds18b20(temp_sensor) => actuators.apply(temp_sensor.wire(), states).await,
+            Probe::Bridge(temp_sensor) => actuators.apply(temp_sensor.wire(), states).await,
+            _ => Ok(()),
+        };
+        if let Err(fault) = result {
+            error!("Output switch fault: {:?} ({:?})", fault, states);     // Debug console
+        }
+        *OUTPUT_FAULT.lock().await = result.is_err();
+    }
+}
+
+// Set the state wanted for an output role and ask the sensor task to switch the 1-Wire outputs if it changed
+async fn set_output(role: OutputRole, on: bool) {
+    if OUTPUT_STATES.lock().await.set(role, on) {
+        OUTPUTS_CHANGED.signal(());
     }
 }
 
+// A DS18B20 compares each conversion against its own TH/TL limits, independently of the filter and controller. Switches on the same bus are ignored.
+// If the search fails the alarm is raised, as the limits can't be checked
+async fn check_alarms<W: OneWire, P: OutputPin>(temp_sensor: &mut Ds18b20<W, P>) {
+    let alarm = match temp_sensor.temperature_alarm().await {
+        Ok(alarm) => alarm,
+        Err(fault) => {
+            warn!("Alarm search failed: {:?}", fault);     // Debug console
+            true
+        }
+    };
+    if alarm && !*TEMP_ALARM.lock().await {
+        let unit = *TEMP_UNIT.lock().await;
+        error!("Sensor hardware alarm: outside {:?} - {:?} deg {}", unit.from_celsius(ALARM_LOW as f32), unit.from_celsius(ALARM_HIGH as f32), unit.symbol());     // Debug console
+    }
+    *TEMP_ALARM.lock().await = alarm;
+}
+
+// Each extra 1-Wire bus runs in its own copy of this task, `index` picks its slot in the shared readings
+#[embassy_executor::task(pool_size = EXTRA_BUS_COUNT)]
+async fn bus_task(index: usize, bus: ExtraBus, mut temp_sensor: Ds18b20<ExtraWire, Output<'static>>) {
+    let (power_desc, power_msg) = setup_ds18b20(&mut temp_sensor).await;
+    info!("{} bus: {} {}", bus.name, power_desc, power_msg);     // Debug console
+    let mut roster = SensorRoster::new();
+    loop {
+        let removed = scan_for_sensors(&mut temp_sensor, &mut roster).await;
+        BUS_SENSOR_REMOVED.lock().await[index] = removed;
+        match temp_sensor.read_temperature().await {
+            Ok(temp) => {
+                let unit = *TEMP_UNIT.lock().await;
+                info!("{} = {:?} deg {}", bus.name, unit.from_celsius(temp), unit.symbol());     // Debug console
+                BUS_TEMPS.lock().await[index] = Some(temp);
+            }
+            Err(fault) => {
+                BUS_ERRORS.lock().await[index].record(fault);
+                warn!("{} probe read failed: {:?} ({:?})", bus.name, fault, BUS_ERRORS.lock().await[index]);     // Debug console
+                BUS_TEMPS.lock().await[index] = None;
+            }
+        }
+        Timer::after_secs(bus.check_in).await;
+    }
+}
+
+// Detect how the DS18B20s on a bus are powered and give them their configuration.
+// Returns the power supply description and any warning for the diagnostics screen.
+async fn setup_ds18b20<W: OneWire, P: OutputPin>(temp_sensor: &mut Ds18b20<W, P>) -> (&'static str, &'static str) {
+    let mut power_msg = "";
+    // Parasite powered sensors can't be polled for completion and need the bus held high while converting
+    match temp_sensor.detect_power_supply().await {
+        Ok(PowerSupply::Parasite) if !temp_sensor.has_strong_pullup() => {
+            power_msg = "NO STRONG PULLUP";
+            warn!("Sensor is parasite powered but no strong pull-up is fitted, conversions may fail");     // Debug console
+        }
+        Ok(_) => {}
+        Err(fault) => {
+            power_msg = fault.description();
+            warn!("Sensor power supply not detected, assuming parasite power: {:?}", fault);     // Debug console
+        }
+    }
+    let power_supply = temp_sensor.power_supply();
+    info!("Sensor power supply: {:?}", power_supply);     // Debug console
+    // Each sensor is read by its ROM code, as a broadcast read collides when there is more than one.
+    // Only sensors whose stored configuration is different have their EEPROM written, to save wear.
+    let mut outdated: Vec<[u8; 8], 8> = Vec::new();
+    if let Ok(devices) = temp_sensor.search_for_roms().await {
+        for rom in devices.iter().flatten().filter(|rom| rom[0] == FAMILY_CODE) {
+            if temp_sensor.read_config_with_rom(rom).await.ok() != Some(SENSOR_CONFIG) {
+                let _ = outdated.push(*rom);
+            }
+        }
+    }
+    match temp_sensor.write_config(SENSOR_CONFIG).await {
+        Ok(()) => {
+            for rom in outdated.iter() {
+                match temp_sensor.copy_scratchpad_with_rom(rom).await {
+                    Ok(()) => info!("Sensor configuration saved to EEPROM: {:X}", rom),     // Debug console
+                    Err(fault) => warn!("Sensor configuration not saved to EEPROM: {:X} {:?}", rom, fault),     // Debug console
+                }
+            }
+        }
+        Err(fault) => warn!("Sensor configuration not verified: {:?}", fault),     // Debug console
+    }
+    (power_supply.description(), power_msg)
+}
+
+// Search a 1-Wire bus for DS18B20s that have been plugged in or unplugged since the last search.
+// New sensors are given the configuration and calibration for their ROM code.
+async fn scan_for_sensors<W: OneWire, P: OutputPin>(temp_sensor: &mut Ds18b20<W, P>, roster: &mut SensorRoster) -> bool {
+    let found = match temp_sensor.search_for_roms().await {
+        Ok(found) => found,
+        Err(SensorError::NoPresence) => [None; 8],  // Every sensor has been unplugged
+        Err(_) => return !roster.missing().is_empty(),
+    };
+    let changes = roster.update(&found);
+    for rom in changes.added.iter() {
+        if rom[0] != FAMILY_CODE {
+            info!("Device added: {:X}", rom);     // Debug console
+            continue;
+        }
+        info!("Sensor added: {:X}", rom);     // Debug console
+        let stored_config = temp_sensor.read_config_with_rom(rom).await.ok();
+        // Only write to the sensor's EEPROM when the stored configuration is different to save wear
+        if stored_config != Some(SENSOR_CONFIG) && temp_sensor.write_config_with_rom(rom, SENSOR_CONFIG).await.is_ok() {
+            if let Err(fault) = temp_sensor.copy_scratchpad_with_rom(rom).await {
+                warn!("Sensor configuration not saved to EEPROM: {:X} {:?}", rom, fault);     // Debug console
+            }
+        }
+        if let Some((_, offset)) = CALIBRATION.iter().find(|(known, _)| known == rom) {
+            temp_sensor.set_offset(rom, *offset);
+        }
+    }
+    for rom in changes.removed.iter() {
+        warn!("Sensor removed: {:X}", rom);     // Debug console
+    }
+    // Read the control probe by its ROM code, so its calibration is used and switches on the bus don't answer too.
+    // It only changes when it has been removed, so plugging in another sensor doesn't move the controller onto it.
+    let probe = roster.choose_probe(temp_sensor.probe());
+    if probe != temp_sensor.probe() {
+        match probe {
+            Some(rom) => warn!("Control probe is now {:X}", rom),     // Debug console
+            None => warn!("No control probe left on the bus"),     // Debug console
+        }
+        temp_sensor.set_probe(probe);
+    }
+    !roster.missing().is_empty()
+}
+
+#[embassy_executor::task]
+async fn humidity_task(mut sht: Sht<I2c0Device, Delay>) {
+    loop {
+        match sht.measure().await {
+            Ok(reading) => {
+                let alarm = reading.humidity > HUMIDITY_LIMIT;
+                if alarm && !*HUMIDITY_ALARM.lock().await {
+                    warn!("High chamber humidity: {:?} %RH", reading.humidity);     // Debug console
+                }
+                let unit = *TEMP_UNIT.lock().await;
+                info!("humidity = {:?} %RH, dew point = {:?} deg {}", reading.humidity, unit.from_celsius(reading.dew_point()), unit.symbol());     // Debug console
+                *HUMIDITY_ALARM.lock().await = alarm;
+                *CHAMBER.lock().await = Some(reading);
+            }
+            Err(fault) => {
+                warn!("Humidity sensor read failed: {:?}", fault);     // Debug console
+                *CHAMBER.lock().await = None;
+            }
+        }
+        Timer::after_secs(HUMIDITY_CHECK_IN).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn pressure_task(mut transducer: PressureTransducer<'static>, mut solenoid: Output<'static>) {
+    let mut valve = SpundingValve::new(None, PRESSURE_UNIT.to_bar(PRESSURE_LIMIT), PRESSURE_UNIT.to_bar(PRESSURE_HYSTERESIS));
+    loop {
+        valve.set_target(*SPUNDING_TARGET.lock().await);
+        match transducer.pressure().await {
+            Ok(pressure) => {
+                let over_limit = valve.over_limit(pressure);
+                if over_limit && !*OVER_PRESSURE.lock().await {
+                    error!("Over pressure: {:?} bar, venting", pressure);     // Debug console
+                }
+                *OVER_PRESSURE.lock().await = over_limit;
+                *PRESSURE_FAULT.lock().await = false;
+                *PRESSURE.lock().await = Some(pressure);
+                let open = valve.update(pressure);
+                solenoid.set_level(if open { Level::High } else { Level::Low });
+                set_output(OutputRole::Valve, open).await;
+            }
+            Err(fault) => {
+                // Without a reading the limit can't be enforced, so vent rather than let the pressure build.
+                // A reading above the transducer's range is most likely a real over pressure.
+                let over_range = fault == SensorError::OverRange;
+                if !*PRESSURE_FAULT.lock().await && !*OVER_PRESSURE.lock().await {
+                    error!("Pressure sensor read failed: {:?}, venting", fault);     // Debug console
+                }
+                *OVER_PRESSURE.lock().await = over_range;
+                *PRESSURE_FAULT.lock().await = !over_range;
+                *PRESSURE.lock().await = None;
+                solenoid.set_high();
+                set_output(OutputRole::Valve, true).await;
+            }
+        }
+        Timer::after_millis(PRESSURE_CHECK_IN_MS).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn enclosure_task(mut monitor: EnclosureMonitor<'static>) {
+    loop {
+        match monitor.temperature().await {
+            Ok(temp) => {
+                let alarm = monitor.check(temp);
+                let unit = *TEMP_UNIT.lock().await;
+                if alarm && !*ENCLOSURE_ALARM.lock().await {
+                    error!("Enclosure over temperature: {:?} deg {}, relays disabled", unit.from_celsius(temp), unit.symbol());     // Debug console
+                }
+                info!("enclosure = {:?} deg {}", unit.from_celsius(temp), unit.symbol());     // Debug console
+                *ENCLOSURE_ALARM.lock().await = alarm;
+                *ENCLOSURE_TEMP.lock().await = Some(temp);
+            }
+            Err(fault) => warn!("Enclosure temperature read failed: {:?}", fault),     // Debug console
+        }
+        Timer::after_secs(ENCLOSURE_CHECK_IN).await;
+    }
+}
+
+#[embassy_executor::task]
+async fn scale_task(mut scale: Hx711<Input<'static>, Output<'static>>) {
+    loop {
+        match scale.weight().await {
+            Ok(weight) => {
+                // CO2 loss is measured from the weight saved at pitching, or the first weight read if there isn't one
+                let start = match *START_WEIGHT.lock().await {
+                    Some(start) => start,
+                    None => {
+                        *START_WEIGHT.lock().await = Some(weight);
+                        SETTINGS_CHANGED.signal(());
+                        weight
+                    }
+                };
+                let progress = FermentProgress::new(start, weight, WORT_VOLUME);
+                info!("weight = {:?} g, CO2 loss = {:?} g, gravity drop = {:?}", weight, progress.co2_loss, progress.gravity_drop);     // Debug console
+                *FERMENT_PROGRESS.lock().await = Some(progress);
+            }
+            Err(fault) => {
+                warn!("Scale read failed: {:?}", fault);     // Debug console
+                *FERMENT_PROGRESS.lock().await = None;
+            }
+        }
+        if let embassy_futures::select::Either::First(command) = embassy_futures::select::select(SCALE_COMMAND.wait(), Timer::after_secs(SCALE_CHECK_IN)).await {
+            match scale_command(&mut scale, command).await {
+                Ok(()) => info!("Scale {:?} done", command),     // Debug console
+                Err(fault) => warn!("Scale {:?} failed: {:?}", command, fault),     // Debug console
+            }
+        }
+    }
+}
+
+/// What the menu asks the scale task to do
+#[derive(Copy, Clone, defmt::Format)]
+enum ScaleCommand {
+    Tare,               // Zero the scale with nothing on it
+    Calibrate(f32),     // Set the scale factor from a known mass (grams) on the tared scale
+    Start,              // Take the fermenter's weight now as the start weight, at pitching
+}
+
+// Carry out a command from the menu and keep the result in the settings
+async fn scale_command(scale: &mut Hx711<Input<'static>, Output<'static>>, command: ScaleCommand) -> Result<(), SensorError> {
+    match command {
+        ScaleCommand::Tare => scale.tare().await?,
+        ScaleCommand::Calibrate(grams) => scale.calibrate(grams).await?,
+        ScaleCommand::Start => {
+            *START_WEIGHT.lock().await = Some(scale.weight().await?);
+            SETTINGS_CHANGED.signal(());
+            return Ok(());
+        }
+    }
+    let config = scale.config();
+    *SCALE_CALIBRATION.lock().await = Some(ScaleCalibration { offset: config.offset, counts_per_gram: config.counts_per_gram });
+    SETTINGS_CHANGED.signal(());
+    Ok(())
+}
+
+#[embassy_executor::task]
+async fn kettle_task(mut thermocouple: Thermocouple<Spi0Device>) {
+    loop {
+        match thermocouple.read_temperature().await {
+            Ok(temp) => {
+                let unit = *TEMP_UNIT.lock().await;
+                info!("kettle = {:?} deg {}", unit.from_celsius(temp), unit.symbol());     // Debug console
+                *KETTLE_TEMP.lock().await = Some(temp);
+                *KETTLE_FAULT.lock().await = None;
+            }
+            Err(fault) => {
+                warn!("Thermocouple read failed: {:?}", fault);     // Debug console
+                *KETTLE_TEMP.lock().await = None;
+                *KETTLE_FAULT.lock().await = Some(fault);
+            }
+        }
+        Timer::after_secs(KETTLE_CHECK_IN).await;
+    }
+}
+
+// The message for a fault that stops the relays from being switched on, if there is one
+async fn fail_safe_message() -> Option<&'static str> {
+    if *ENCLOSURE_ALARM.lock().await {
+        Some(" ENCLOSURE HOT! ")
+    }
+    else if *TEMP_ALARM.lock().await {
+        Some("  TEMP ALARM!   ")
+    }
+    else {
+        None
+    }
+}
+
+// The message for line 4 when no relay is on: an alarm, or the chamber humidity, fermenter pressure, fermentation progress, extra bus and kettle temperatures in turn
+async fn status_message() -> String<16> {
+    let mut string: String<16> = String::new();
+    if *OVER_PRESSURE.lock().await {
+        let _ = string.push_str(" OVER PRESSURE! ");
+        return string;
+    }
+    if *PRESSURE_FAULT.lock().await {
+        let _ = string.push_str(" PRESSURE FAULT ");
+        return string;
+    }
+    if *SENSOR_REMOVED.lock().await || BUS_SENSOR_REMOVED.lock().await.iter().any(|removed| *removed) {
+        let _ = string.push_str(" SENSOR REMOVED ");
+        return string;
+    }
+    if *OUTPUT_FAULT.lock().await {
+        let _ = string.push_str(" OUTPUT FAULT!  ");
+        return string;
+    }
+    if *HUMIDITY_ALARM.lock().await {
+        let _ = string.push_str(" HIGH HUMIDITY! ");
+        return string;
+    }
+    let chamber = *CHAMBER.lock().await;
+    let pressure = *PRESSURE.lock().await;
+    let progress = *FERMENT_PROGRESS.lock().await;
+    let bus_temps = *BUS_TEMPS.lock().await;
+    let kettle = *KETTLE_TEMP.lock().await;
+    let kettle_fault = *KETTLE_FAULT.lock().await;
+    let unit = *TEMP_UNIT.lock().await;
+    // Only rotate through the pages that have a reading (or a thermocouple fault) to show
+    let pages = [chamber.is_some(), pressure.is_some(), progress.is_some(), bus_temps[0].is_some(), bus_temps[1].is_some(), bus_temps[2].is_some(), kettle.is_some() || kettle_fault.is_some()];
+    let available = pages.iter().filter(|page| **page).count();
+    if available == 0 {
+        return string;
+    }
+    let page = (Instant::now().as_secs() / STATUS_PAGE_SECS) as usize % available;     // Moves on with time, however often the line is redrawn
+    match pages.iter().enumerate().filter(|(_, page)| **page).nth(page).map(|(index, _)| index) {
+        Some(0) => write_humidity(&mut string, chamber.unwrap(), unit),
+        Some(1) => write_pressure(&mut string, pressure.unwrap(), *SPUNDING_TARGET.lock().await),
+        Some(2) => write_progress(&mut string, progress.unwrap()),
+        Some(bus @ 3..=5) => write_bus(&mut string, EXTRA_BUSES[bus - 3].map_or("", |bus| bus.name), bus_temps[bus - 3].unwrap(), unit),
+        Some(6) => match kettle {
+            Some(temp) => write_kettle(&mut string, temp, unit),
+            None => { let _ = string.push_str(kettle_fault.unwrap().description()); }
+        },
+        _ => {}
+    }
+    string
+}
+
+// Chamber humidity and dew point for the status line
+fn write_humidity(string: &mut String<16>, reading: HumidityReading, unit: TemperatureUnit) {
+    let _ = write!(string, "RH {:.0}% DP {:.1}", reading.humidity, unit.from_celsius(reading.dew_point()));
+}
+
+// Fermenter pressure (and the spunding target if there is one) for the status line
+fn write_pressure(string: &mut String<16>, bar: f32, target: Option<f32>) {
+    let pressure = PRESSURE_UNIT.from_bar(bar);
+    let _ = match target {
+        Some(target) => write!(string, "P {:.1}/{:.1} {}", pressure, PRESSURE_UNIT.from_bar(target), PRESSURE_UNIT.symbol()),
+        None => write!(string, "P {:.1} {}", pressure, PRESSURE_UNIT.symbol()),
+    };
+}
+
+// CO2 lost and the estimated gravity drop for the status line
+fn write_progress(string: &mut String<16>, progress: FermentProgress) {
+    let _ = write!(string, "CO2 {:.0}g -{:.3}", progress.co2_loss, progress.gravity_drop);
+}
+
+// Temperature of the probe on an extra 1-Wire bus for the status line
+fn write_bus(string: &mut String<16>, name: &str, temp: f32, unit: TemperatureUnit) {
+    let _ = write!(string, "{} {:.1} {}", name, unit.from_celsius(temp), unit.symbol());
+}
+
+// Boil kettle / HLT temperature from the thermocouple for the status line
+fn write_kettle(string: &mut String<16>, temp: f32, unit: TemperatureUnit) {
+    let _ = write!(string, "Kettle {:.1} {}", unit.from_celsius(temp), unit.symbol());
+}
+
 // Convert a f32 value into a string
 fn f32_to_string(value: f32) -> String<16> {
     let mut string: String<16> = String::new();
@@ -85,6 +787,13 @@ fn f32_to_string(value: f32) -> String<16> {
     string
 }
 
+// Convert a u32 value into a string
+fn u32_to_string(value: u32) -> String<16> {
+    let mut string: String<16> = String::new();
+    let _ = write!(&mut string, "{}", value);
+    string
+}
+
 // Round to the nearest integer value
 fn round(x: f32) -> i32 {
     if x >= 0.0 {
@@ -94,23 +803,118 @@ fn round(x: f32) -> i32 {
     }
 }
 
-// Save the target temperature to flash memory
-async fn save_target_temp(flash: &mut Flash<'_, FLASH, Async, FLASH_SIZE>, temp: f32) {
-    let bytes = temp.to_le_bytes();
+// Save the target temperature, the temperature unit and the scale settings to flash memory
+async fn save_settings(flash: &mut Flash<'_, FLASH, Async, FLASH_SIZE>) {
+    let settings = Settings {
+        target: *TARGET_TEMP.lock().await,
+        unit: *TEMP_UNIT.lock().await,
+        scale: *SCALE_CALIBRATION.lock().await,
+        start_weight: *START_WEIGHT.lock().await,
+    };
     flash.blocking_erase(ADDR_OFFSET, ADDR_OFFSET + ERASE_SIZE as u32).unwrap();
-    flash.blocking_write(ADDR_OFFSET, &bytes).unwrap();
+    flash.blocking_write(ADDR_OFFSET, &settings.to_bytes()).unwrap();
 }
 
-// Load the target temperature from flash memory
-async fn load_target_temp(flash: &mut Flash<'_, FLASH, Async, FLASH_SIZE>) -> Option<f32> {
-    let mut bytes = [0u8; 4];
+// Load the settings from flash memory, saving them again in the current layout if they were saved by older firmware
+async fn load_settings(flash: &mut Flash<'_, FLASH, Async, FLASH_SIZE>) -> Option<Settings> {
+    let mut bytes = [0u8; SETTINGS_SIZE];
     flash.read(ADDR_OFFSET, &mut bytes).await.unwrap();
-    let temp = f32::from_le_bytes(bytes);
-    if temp >= MIN_TEMP && temp <= MAX_TEMP {
-        Some(temp)
-    } else {
-        None
+    let (settings, migrated) = match Settings::from_bytes(&bytes, DEFAULT_TEMP_UNIT) {
+        Loaded::Current(settings) => (settings, false),
+        Loaded::Migrated(settings) => (settings, true),
+        Loaded::Invalid => return None,
+    };
+    if !(MIN_TEMP..=MAX_TEMP).contains(&settings.target) {
+        return None;
+    }
+    *TARGET_TEMP.lock().await = settings.target;
+    *TEMP_UNIT.lock().await = settings.unit;
+    *SCALE_CALIBRATION.lock().await = settings.scale;
+    *START_WEIGHT.lock().await = settings.start_weight;
+    if migrated {
+        info!("Migrating saved settings to version {:?}", SETTINGS_VERSION);     // Debug console
+        save_settings(flash).await;
+    }
+    Some(settings)
+}
+
+// Step the target temperature by a setpoint step of the unit in use, within the limits. Returns false if it is already at the limit.
+async fn step_target(up: bool) -> bool {
+    let unit = *TEMP_UNIT.lock().await;
+    let (min, max) = unit.limits(MIN_TEMP, MAX_TEMP);
+    let target = unit.from_celsius(*TARGET_TEMP.lock().await);
+    let target = match up {
+        true if target < max => unit.round_to_step(target + unit.step()).min(max),
+        false if target > min => unit.round_to_step(target - unit.step()).max(min),
+        _ => return false,
+    };
+    *TARGET_TEMP.lock().await = unit.to_celsius(target);
+    *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await; // Update the variance
+    true
+}
+
+// Set the menu's target entry up for the unit in use
+async fn set_menu_unit(menu: &mut Menu<Screen>) {
+    let unit = *TEMP_UNIT.lock().await;
+    let (min, max) = unit.limits(MIN_TEMP, MAX_TEMP);
+    menu.set_kind(MENU_TARGET, EntryKind::Number { min, max, step: unit.step() });
+    menu.set_value(MENU_TARGET, Value::Number(unit.round_to_step(unit.from_celsius(*TARGET_TEMP.lock().await)).clamp(min, max)));
+    menu.set_value(MENU_UNIT, Value::Choice(unit.index()));
+}
+
+
+// Indicates the readings screen is showing, so status messages can be written to its bottom line
+async fn showing_readings(menu: &Menu<Screen>) -> bool {
+    *DISPLAY_ON.lock().await && !*SHOW_GRAPH.lock().await && !menu.is_open()
+}
+
+// Draw the home screen from the latest readings
+async fn show_home(display: &mut MainDisplay, heating: bool, cooling: bool, message: &str, profile: &ProfileRunner<'_>, usb_sense: &Input<'_>) {
+    let fault = *NO_DEVICE.lock().await;
+    let icon = match (fault, heating, cooling) {
+        (true, _, _) => HomeIcon::Fault,
+        (false, true, _) => HomeIcon::Heating,
+        (false, false, true) => HomeIcon::Cooling,
+        _ => HomeIcon::Idle,
+    };
+    let home = HomeScreen {
+        temp: if fault { None } else { Some(*CURRENT_TEMP.lock().await) },
+        target: *TARGET_TEMP.lock().await,
+        icon,
+        step: if PROFILE.is_empty() { None } else { Some((profile.step_index() + 1, PROFILE.len())) },
+        message,
+        uptime: Instant::now().as_secs(),
+        link: usb_sense.is_high(),
+    };
+    display.show_home(&home).await;
+}
+
+// Take the key press waiting to be handled. Key0 is Next / Select (held) and key1 is Previous / Back (held).
+async fn take_key() -> Option<Key> {
+    let flags = [
+        (&DISPLAY_KEY0_HELD, Key::Select),
+        (&DISPLAY_KEY1_HELD, Key::Back),
+        (&DISPLAY_KEY0_PRESSED, Key::Next),
+        (&DISPLAY_KEY1_PRESSED, Key::Previous),
+    ];
+    let mut key = None;
+    for (flag, flag_key) in flags {
+        let mut pressed = flag.lock().await;
+        if *pressed && key.is_none() {
+            key = Some(flag_key);
+        }
+        *pressed = false;   // Turn off the press flag once it has been handled
     }
+    key
+}
+
+// The display orientation chosen in the menu
+fn menu_orientation(menu: &Menu<Screen>) -> (Rotation, bool) {
+    let rotation = match menu.value(MENU_ROTATE) {
+        Value::Choice(index) => ROTATIONS[index % ROTATIONS.len()],
+        _ => DISPLAY_ROTATION,
+    };
+    (rotation, menu.value(MENU_MIRROR) == Value::Choice(1))
 }
 
 
@@ -121,26 +925,36 @@ async fn gpio_task(mut key0: Input<'static>, mut key1: Input<'static>) {
         let key0_future = key0.wait_for_falling_edge();
         let key1_future = key1.wait_for_falling_edge();
 
-        if *DISPLAY_ON.lock().await {
-            // Wait for either button to be pressed
-            match embassy_futures::select::select(key0_future, key1_future).await {
-                // Key0 was pressed
-                embassy_futures::select::Either::First(_) => {
-                    *PIN_INTERRUPT.lock().await = true;
+        // Wait for either button to be pressed
+        match embassy_futures::select::select(key0_future, key1_future).await {
+            // Key0 was pressed
+            embassy_futures::select::Either::First(_) => {
+                let pressed = Instant::now();
+                while key0.is_low() {
+                    Timer::after_millis(10).await;  // Wait for the button to be released
+                }
+                *PIN_INTERRUPT.lock().await = true;
+                if pressed.elapsed().as_millis() >= LONG_PRESS_MS {
+                    *DISPLAY_KEY0_HELD.lock().await = true;
+                    info!("Key0 held");     // Debug colsole
+                } else {
                     *DISPLAY_KEY0_PRESSED.lock().await = true;
                     info!("Key0 pressed");      // Debug colsole
-                    while key0.is_low() {
-                        Timer::after_millis(10).await;  // Wait for the button to be released
-                    }
                 }
-                // Key1 was pressed
-                embassy_futures::select::Either::Second(_) => {
-                    *PIN_INTERRUPT.lock().await = true;
+            }
+            // Key1 was pressed
+            embassy_futures::select::Either::Second(_) => {
+                let pressed = Instant::now();
+                while key1.is_low() {
+                    Timer::after_millis(10).await;  // Wait for the button to be released
+                }
+                *PIN_INTERRUPT.lock().await = true;
+                if pressed.elapsed().as_millis() >= LONG_PRESS_MS {
+                    *DISPLAY_KEY1_HELD.lock().await = true;
+                    info!("Key1 held");     // Debug colsole
+                } else {
                     *DISPLAY_KEY1_PRESSED.lock().await = true;
                     info!("Key1 pressed");      // Debug colsole
-                    while key1.is_low() {
-                        Timer::after_millis(10).await;  // Wait for the button to be released
-                    }
                 }
             }
         }
@@ -156,34 +970,146 @@ async fn main(_spawner: Spawner) {
     let peripherals = embassy_rp::init(Default::default());
     let mut delay = Delay;
 
-    // Read from flash memory if the target temperature has been set previously
+    // Read from flash memory if the target temperature and unit have been set previously
     let mut flash = Flash::<_, Async, FLASH_SIZE>::new(peripherals.FLASH, peripherals.DMA_CH1);
-    if let Some(saved_temp) = load_target_temp(&mut flash).await {
-        *TARGET_TEMP.lock().await = saved_temp;
-    }
+    let _ = load_settings(&mut flash).await;
  
+    // The ADC is shared by the analog inputs
+    let adc = ADC.init(Mutex::new(Adc::new(peripherals.ADC, Irqs, adc::Config::default())));
+
+    // Spawn the task that watches the temperature inside the enclosure
+    let enclosure_channel = adc::Channel::new_temp_sensor(peripherals.ADC_TEMP_SENSOR);
+    _spawner.spawn(enclosure_task(EnclosureMonitor::new(adc, enclosure_channel, ENCLOSURE_LIMIT, ENCLOSURE_HYSTERESIS))).unwrap();
+
     // Thermometer pins
     let mut pio = Pio::new(peripherals.PIO0, Irqs);
-    // Set up onewire
-    let prg = PioOneWireProgram::new(&mut pio.common);
-    let onewire = PioOneWire::new(&mut pio.common, pio.sm0, peripherals.PIN_16, &prg);
-    // Set up thermometer
-    let mut temp_sensor = Ds18b20::new(onewire);
-    let _ = temp_sensor.set_resolution(Resolution::Bits12).await; // Set the resolution to 12 bits (0.0625 degrees C)
+    let prg = PioOneWireProgram::new(&mut pio.common);  // Shared by every 1-Wire bus, each on its own state machine
+    // I2C0 is shared by the humidity sensor, the DS2482 bridge and the display when it is on I2C
+    let i2c0_bus = if HUMIDITY_MODEL.is_some() || matches!(PROBE_TYPE, ProbeType::Ds2482) || DISPLAY_BUS == DisplayBus::I2c {
+        let mut i2c_config = i2c::Config::default();
+        i2c_config.frequency = 400_000;
+        Some(&*I2C0_BUS.init(Mutex::new(I2c::new_async(peripherals.I2C0, peripherals.PIN_21, peripherals.PIN_20, Irqs, i2c_config))))
+    } else {
+        None
+    };
+    let mut power_desc = "N/A";
+    let mut power_msg = "";
+    // SPI0 is shared by the RTD converter (GPIO5 = CS) and the thermocouple (GPIO28 = CS)
+    let spi0_bus = if matches!(PROBE_TYPE, ProbeType::Rtd) || THERMOCOUPLE.is_some() {
+        Some(&*SPI0_BUS.init(Mutex::new(Spi::new(
+            peripherals.SPI0,
+            peripherals.PIN_2,      // Serial Clock
+            peripherals.PIN_3,      // Master Out Slave In
+            peripherals.PIN_4,      // Master In Slave Out
+            peripherals.DMA_CH2,    // TX DMA channel
+            peripherals.DMA_CH3,    // RX DMA channel
+            spi::Config::default(),
+        ))))
+    } else {
+        None
+    };
+    let probe = match PROBE_TYPE {
+        ProbeType::Ds18b20 => {
+            // Set up onewire
+            let onewire = PioOneWire::new(&mut pio.common, pio.sm0, peripherals.PIN_16, &prg);
+            // Set up thermometer
+            let mut temp_sensor = Ds18b20::new(onewire);
+            if SENSOR_STRONG_PULLUP {
+                temp_sensor.set_strong_pullup(Output::new(peripherals.PIN_18, Level::Low));
+            }
+            (power_desc, power_msg) = setup_ds18b20(&mut temp_sensor).await;
+            Probe::Ds18b20(temp_sensor)
+        }
+        ProbeType::Ds2482 => {
+            // DS2482 on I2C0 alongside the humidity sensor, sensors on `BRIDGE_CHANNEL`
+            let mut bridge = Ds2482::new(I2cDevice::new(i2c0_bus.unwrap()), ds2482::DEFAULT_ADDRESS);
+            if let Err(fault) = bridge.init().await {
+                error!("DS2482 bridge not found: {:?}", fault);     // Debug console
+            }
+            if let Some(channel) = BRIDGE_CHANNEL {
+                if let Err(fault) = bridge.select_channel(channel).await {
+                    error!("DS2482 channel {} not selected: {:?}", channel, fault);     // Debug console
+                }
+            }
+            let mut temp_sensor = Ds18b20::new(bridge);
+            (power_desc, power_msg) = setup_ds18b20(&mut temp_sensor).await;
+            Probe::Bridge(temp_sensor)
+        }
+        ProbeType::Ntc => {
+            let channel = adc::Channel::new_pin(peripherals.PIN_26, Pull::None);
+            Probe::Ntc(NtcThermistor::new(adc, channel, NTC_CONFIG))
+        }
+        ProbeType::Rtd => {
+            // MAX31865 uses SPI mode 1
+            let mut spi_config = spi::Config::default();
+            spi_config.frequency = 1_000_000;
+            spi_config.phase = spi::Phase::CaptureOnSecondTransition;
+            spi_config.polarity = spi::Polarity::IdleLow;
+            let spi_device = SpiDeviceWithConfig::new(spi0_bus.unwrap(), Output::new(peripherals.PIN_5, Level::High), spi_config);
+            let mut rtd = Max31865::new(spi_device, RTD_CONFIG);
+            if let Err(fault) = rtd.init().await {
+                error!("RTD fault found at start up: {:?}", fault);     // Debug console
+            }
+            Probe::Rtd(rtd)
+        }
+    };
+    // Set up the boil kettle / HLT thermocouple and spawn its task
+    if let Some(kind) = THERMOCOUPLE {
+        let mut spi_config = spi::Config::default();
+        spi_config.frequency = 4_000_000;  // Both converters use SPI mode 0, the default
+        let spi_device = SpiDeviceWithConfig::new(spi0_bus.unwrap(), Output::new(peripherals.PIN_28, Level::High), spi_config);
+        _spawner.spawn(kettle_task(Thermocouple::new(spi_device, kind))).unwrap();
+    }
+
+    // Set up the extra 1-Wire buses, each on its own state machine, and spawn a task for each
+    let [bus1, bus2, bus3] = EXTRA_BUSES;
+    if let Some(bus) = bus1 {
+        let onewire = ExtraWire::Sm1(PioOneWire::new(&mut pio.common, pio.sm1, peripherals.PIN_13, &prg));
+        _spawner.spawn(bus_task(0, bus, Ds18b20::new(onewire))).unwrap();
+    }
+    if let Some(bus) = bus2 {
+        let onewire = ExtraWire::Sm2(PioOneWire::new(&mut pio.common, pio.sm2, peripherals.PIN_19, &prg));
+        _spawner.spawn(bus_task(1, bus, Ds18b20::new(onewire))).unwrap();
+    }
+    if let Some(bus) = bus3 {
+        let onewire = ExtraWire::Sm3(PioOneWire::new(&mut pio.common, pio.sm3, peripherals.PIN_22, &prg));
+        _spawner.spawn(bus_task(2, bus, Ds18b20::new(onewire))).unwrap();
+    }
+
+    // Set up the filter between the sensor and the controller
+    let temp_filter = ReadingFilter::<FILTER_MEDIAN_SIZE>::new(FilterConfig {
+        ema_alpha: FILTER_EMA_ALPHA,
+        max_rate: FILTER_MAX_RATE,
+        max_step: FILTER_MAX_STEP,
+        reseed_after: FILTER_RESEED_AFTER,
+    });
 
 
     // Initialise the display and show the splash screen
-    let display_peripherals = DisplayPeripherals::new(
-        Output::new(peripherals.PIN_8, Level::Low),  // Data/Command
-        Output::new(peripherals.PIN_9, Level::High), // Chip Select
-        Output::new(peripherals.PIN_12, Level::Low), // Reset
-        peripherals.PIN_10,     // Serial Clock
-        peripherals.PIN_11,     // Master Out Slave In
-        peripherals.SPI1,       // SPI peripheral
-        peripherals.DMA_CH0,    // DMA channel
-    );
-    let mut display = Display::new(display_peripherals);
-    let _ = display.initialise().await;
+    let mut display: MainDisplay = match DISPLAY_BUS {
+        DisplayBus::Spi => {
+            let display_peripherals = DisplayPeripherals::new(
+                Output::new(peripherals.PIN_8, Level::Low),  // Data/Command
+                Output::new(peripherals.PIN_9, Level::High), // Chip Select
+                Output::new(peripherals.PIN_12, Level::Low), // Reset
+                peripherals.PIN_10,     // Serial Clock
+                peripherals.PIN_11,     // Master Out Slave In
+                peripherals.SPI1,       // SPI peripheral
+                peripherals.DMA_CH0,    // DMA channel
+            );
+            Display::new_spi(display_peripherals)
+        }
+        DisplayBus::I2c => {
+            let rst = Output::new(peripherals.PIN_12, Level::Low);
+            Display::new_i2c(I2cDevice::new(i2c0_bus.unwrap()), DEFAULT_I2C_ADDRESS, Some(rst))
+        }
+    };
+    let _ = display.set_orientation(DISPLAY_ROTATION, DISPLAY_MIRROR);     // Checked against the panel when building
+    display.set_idle_policy(IDLE_POLICY);
+    display.set_temperature_unit(*TEMP_UNIT.lock().await);
+    if display.initialise().await.is_err() {
+        error!("Display not found, running without it");     // Debug console
+    }
     delay.delay_ms(10).await;
     let _ = display.show_splash_screen().await;
 
@@ -194,20 +1120,44 @@ async fn main(_spawner: Spawner) {
     // Spawn the GPIO task to handle interrupts
     _spawner.spawn(gpio_task(display_key0, display_key1)).unwrap();
 
-    let _ = get_current_temp(&mut temp_sensor).await;    // Get a temperature reading
-    let mut msg = "";
-    if *NO_DEVICE.lock().await {
-        msg = "SENSOR NOT FOUND";
-        let _ = display.clear_all().await;
-        let _ = display.refresh_line_4(msg).await;
-        let _ = display.show().await;
+    // Set up the pressure transducer and spunding valve solenoid and spawn their task
+    *SPUNDING_TARGET.lock().await = SPUNDING_PRESSURE.map(|target| PRESSURE_UNIT.to_bar(target));
+    if let Some(config) = TRANSDUCER_CONFIG {
+        let channel = adc::Channel::new_pin(peripherals.PIN_27, Pull::None);
+        let solenoid = Output::new(peripherals.PIN_14, Level::Low);
+        _spawner.spawn(pressure_task(PressureTransducer::new(adc, channel, config), solenoid)).unwrap();
     }
-    else {
-        let cur_tmp = f32_to_string(*CURRENT_TEMP.lock().await);
-        let tar_tmp = f32_to_string(*TARGET_TEMP.lock().await);
-        let cur_var = f32_to_string(*CURRENT_VARIANCE.lock().await);
-        let _ = display.refresh_readings(cur_tmp.as_str(), tar_tmp.as_str(), cur_var.as_str(), msg).await;
+
+    // Set up the fermenter scale and spawn its task
+    if let Some(mut config) = SCALE_CONFIG {
+        if let Some(calibration) = *SCALE_CALIBRATION.lock().await {
+            config.offset = calibration.offset;     // Tared and calibrated from the menu
+            config.counts_per_gram = calibration.counts_per_gram;
+        }
+        let dout = Input::new(peripherals.PIN_0, Pull::None);
+        let sck = Output::new(peripherals.PIN_1, Level::Low);
+        _spawner.spawn(scale_task(Hx711::new(dout, sck, config))).unwrap();
     }
+
+    // Set up the chamber humidity sensor and spawn its task
+    if let Some(model) = HUMIDITY_MODEL {
+        _spawner.spawn(humidity_task(Sht::new(I2cDevice::new(i2c0_bus.unwrap()), Delay, DEFAULT_ADDRESS, model))).unwrap();
+    }
+
+    // Spawn the sensor task to take readings in the background
+    _spawner.spawn(sensor_task(probe, temp_filter)).unwrap();
+
+    TEMP_REQUEST.signal(());    // Get a temperature reading
+    TEMP_READY.wait().await;
+
+    // Show the diagnostics screen once the first reading has been taken
+    let errors = u32_to_string(PROBE_ERRORS.lock().await.total());
+    let enclosure = match *ENCLOSURE_TEMP.lock().await {
+        Some(temp) => f32_to_string(TEMP_UNIT.lock().await.from_celsius(temp)),
+        None => String::try_from("N/A").unwrap(),
+    };
+    let _ = display.show_diagnostics(power_desc, errors.as_str(), enclosure.as_str(), power_msg).await;
+    Timer::after_secs(DIAGNOSTICS_TIME).await;
     
     //delay.delay_ms(5000).await; // ** NB ** Remove after testing
 
@@ -215,53 +1165,140 @@ async fn main(_spawner: Spawner) {
     let mut heating_relay = Output::new(peripherals.PIN_6, Level::Low); // Relay 1 for heating
     let mut cooling_relay = Output::new(peripherals.PIN_7, Level::Low); // Relay 2 for cooling
 
+    // Start the fermentation profile
+    let mut profile = ProfileRunner::new(PROFILE, Instant::now().as_secs());
+    if let Some(step) = profile.current() {
+        *TARGET_TEMP.lock().await = step.target_temp;
+        *SPUNDING_TARGET.lock().await = step.pressure.map(|target| PRESSURE_UNIT.to_bar(target));
+    }
+
+    // Show the home screen
+    let usb_sense = Input::new(peripherals.PIN_24, Pull::None);    // VBUS sense, high when USB is connected
+    let status = status_message().await;
+    show_home(&mut display, false, false, status.as_str(), &profile, &usb_sense).await;
+
     // Main loop
     info!("Begin loop logic");      // Debug colsole
+    let mut reading_pending = false;    // Indicates that the sensor task is taking a reading
+    let mut history_sample = HistorySample::default();  // Relay states gathered for the next history sample
+    let mut next_history = Instant::now().as_secs();    // When the next history sample is taken
+    let mut next_clock = 0;     // When the clock screen is next redrawn
+    let mut menu = Menu::new(&MENU);
+    let rotation = ROTATIONS.iter().position(|rotation| *rotation == DISPLAY_ROTATION).unwrap_or(0);
+    menu.set_value(MENU_ROTATE, Value::Choice(rotation));
+    menu.set_value(MENU_MIRROR, Value::Choice(DISPLAY_MIRROR as usize));
+    set_menu_unit(&mut menu).await;
+    for entry in [MENU_TARE, MENU_CALIBRATE, MENU_PITCHED] {
+        menu.set_locked(entry, SCALE_CONFIG.is_none());
+    }
     loop {
         // Check if a button was pressed
         if *PIN_INTERRUPT.lock().await {
-            if *DISPLAY_KEY0_PRESSED.lock().await && *NO_DEVICE.lock().await == false {
-                if *TARGET_TEMP.lock().await < MAX_TEMP && *DISPLAY_ON.lock().await {
-                    *TARGET_TEMP.lock().await += 0.5;
-                    *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await; // Update the variance
-                    save_target_temp(&mut flash, *TARGET_TEMP.lock().await).await;  // Save new temp
+            let key = take_key().await;
+            if let (Some(key), true) = (key, *DISPLAY_ON.lock().await) {  // A press while the display is off only wakes it
+                if menu.is_open() {
+                    menu.set_locked(MENU_TARGET, *NO_DEVICE.lock().await);    // Like the short presses, the target can't be changed without a sensor
+                    match menu.handle(key) {
+                        MenuEvent::Open(screen) => *SHOW_GRAPH.lock().await = screen == Screen::Graph,
+                        MenuEvent::Changed(MENU_TARGET, Value::Number(target)) => {
+                            *TARGET_TEMP.lock().await = TEMP_UNIT.lock().await.to_celsius(target);
+                            *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await; // Update the variance
+                            save_settings(&mut flash).await;  // Save new temp
+                        }
+                        MenuEvent::Changed(MENU_UNIT, Value::Choice(unit)) => {
+                            *TEMP_UNIT.lock().await = TemperatureUnit::ALL[unit];
+                            display.set_temperature_unit(TemperatureUnit::ALL[unit]);
+                            set_menu_unit(&mut menu).await;     // The target keeps its degrees C value
+                            save_settings(&mut flash).await;  // Save new unit
+                        }
+                        MenuEvent::Changed(MENU_ROTATE | MENU_MIRROR, _) => {
+                            let (rotation, mirror) = menu_orientation(&menu);
+                            let _ = display.set_orientation(rotation, mirror);     // The menu only offers rotations the panel supports
+                        }
+                        MenuEvent::Action(MENU_TARE) => SCALE_COMMAND.signal(ScaleCommand::Tare),
+                        MenuEvent::Action(MENU_PITCHED) => SCALE_COMMAND.signal(ScaleCommand::Start),
+                        MenuEvent::Changed(MENU_CALIBRATE, Value::Number(grams)) => {
+                            SCALE_COMMAND.signal(ScaleCommand::Calibrate(grams));
+                            menu.set_value(MENU_CALIBRATE, Value::Number(0.0));    // So the same mass can be used again
+                        }
+                        _ => {}
+                    }
                 }
-                *DISPLAY_KEY0_PRESSED.lock().await = false;     // Turn off the key0 press flag after it has been handled
-            }
-            if *DISPLAY_KEY1_PRESSED.lock().await && *NO_DEVICE.lock().await == false {
-                if *TARGET_TEMP.lock().await > MIN_TEMP && *DISPLAY_ON.lock().await {
-                    *TARGET_TEMP.lock().await -= 0.5;
-                    *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await; // Update the variance
-                    save_target_temp(&mut flash, *TARGET_TEMP.lock().await).await;  // Save new temp
+                else {
+                    match key {
+                        Key::Select => {
+                            set_menu_unit(&mut menu).await;     // The target may have been changed by a short press or the profile
+                            menu.open();
+                        }
+                        Key::Back => {
+                            let show_graph = !*SHOW_GRAPH.lock().await;
+                            *SHOW_GRAPH.lock().await = show_graph;
+                        }
+                        // A short press on the graph goes back to the readings without changing the target
+                        _ if *SHOW_GRAPH.lock().await => *SHOW_GRAPH.lock().await = false,
+                        _ if *NO_DEVICE.lock().await => {}
+                        Key::Next => {
+                            if step_target(true).await {
+                                save_settings(&mut flash).await;  // Save new temp
+                            }
+                        }
+                        Key::Previous => {
+                            if step_target(false).await {
+                                save_settings(&mut flash).await;  // Save new temp
+                            }
+                        }
+                    }
                 }
-                *DISPLAY_KEY1_PRESSED.lock().await = false;     // Turn off the key1 press flag after it has been handled
             }
             if *DISPLAY_ON.lock().await == false {
                 *DISPLAY_ON.lock().await = true;    // To wake up the display if it was off
             }
-            if *NO_DEVICE.lock().await {
-                let _ = display.clear_all().await;
-                let _ = display.refresh_line_4("SENSOR NOT FOUND").await;
-                let _ = display.show().await;
+            display.set_idle_stage(IdleStage::Awake).await;     // Restore the contrast, or switch the panel back on
+            if menu.is_open() {
+                let _ = display.show_menu(&menu.render()).await;
+            }
+            else if *SHOW_GRAPH.lock().await && !*NO_DEVICE.lock().await {
+                let _ = display.show_graph(&*HISTORY.lock().await, HISTORY_HOURS).await;
             }
             else {
-                let cur_tmp = f32_to_string(*CURRENT_TEMP.lock().await);
-                let tar_tmp = f32_to_string(*TARGET_TEMP.lock().await);
-                let cur_var = f32_to_string(*CURRENT_VARIANCE.lock().await);
-                let mut msg = "";
+                let status = status_message().await;
+                let mut msg = status.as_str();
                 if heating_relay.is_set_high() { msg = "   HEATING ON   "; }
                 if cooling_relay.is_set_high() { msg = "   COOLING ON   "; }
-                let _ = display.refresh_readings(cur_tmp.as_str(), tar_tmp.as_str(), cur_var.as_str(), msg).await;
+                show_home(&mut display, heating_relay.is_set_high(), cooling_relay.is_set_high(), msg, &profile, &usb_sense).await;
             }
             *PIN_INTERRUPT.lock().await = false;    // Turn off the interrupt flag after it has been handled
         }
         else {
             let now = Instant::now().as_secs();
-            // Check how long the display has been on for
-            if now >= *LAST_DISPLAY.lock().await + DISPLAY_TIMEOUT as u64 {
-                *DISPLAY_ON.lock().await = false;    // Turn off the display
-                let _ = display.clear_all().await;
-                let _ = display.show().await;
+            // Check if the profile has moved on to its next step
+            if let Some(step) = profile.update(now, *FERMENT_PROGRESS.lock().await) {
+                let unit = *TEMP_UNIT.lock().await;
+                info!("Profile step {:?}: {:?} deg {}", profile.step_index() + 1, unit.from_celsius(step.target_temp), unit.symbol());     // Debug console
+                *TARGET_TEMP.lock().await = step.target_temp;
+                *SPUNDING_TARGET.lock().await = step.pressure.map(|target| PRESSURE_UNIT.to_bar(target));
+                *CURRENT_VARIANCE.lock().await = *TARGET_TEMP.lock().await - *CURRENT_TEMP.lock().await;
+            }
+
+            // Save settings changed by another task, like the scale being tared
+            if SETTINGS_CHANGED.try_take().is_some() {
+                save_settings(&mut flash).await;
+            }
+
+            // Check how long it has been since a key was pressed
+            let stage = IDLE_POLICY.stage(now.saturating_sub(*LAST_DISPLAY.lock().await));
+            if stage != display.idle_stage() {
+                display.set_idle_stage(stage).await;
+                if stage == IdleStage::Clock || stage == IdleStage::Asleep {
+                    *DISPLAY_ON.lock().await = false;    // Only the clock is drawn from now on
+                    menu.close();
+                    next_clock = now;
+                }
+            }
+            if display.idle_stage() == IdleStage::Clock && now >= next_clock {
+                let temp = if *NO_DEVICE.lock().await { None } else { Some(*CURRENT_TEMP.lock().await) };
+                display.show_clock(now, temp).await;
+                next_clock = now + 60 - now % 60;   // Redraw on the minute
             }
             
             // Set the check interval based on whether a device was detected
@@ -270,6 +1307,16 @@ async fn main(_spawner: Spawner) {
                 false => CHECK_IN as u64,
             };
 
+            // Drop to the fail-safe state straight away if the enclosure overheats
+            if *ENCLOSURE_ALARM.lock().await && *RELAY_ON.lock().await {
+                heating_relay.set_low();
+                cooling_relay.set_low();
+                *RELAY_ON.lock().await = false;
+                if showing_readings(&menu).await {
+                    show_home(&mut display, false, false, " ENCLOSURE HOT! ", &profile, &usb_sense).await;
+                }
+            }
+
             // Check if it is time to switch off the relays
             if *RELAY_ON.lock().await && now >= *SWITCH_OFF_RELAYS.lock().await {
                 // Switch off the relays
@@ -278,37 +1325,48 @@ async fn main(_spawner: Spawner) {
                 info!("Relays off");     // Debug colsole
                 *RELAY_ON.lock().await = false;
                 // Clear the messsage line
-                if *DISPLAY_ON.lock().await {
-                    let _ =  display.clear_line_4().await;
-                    let _ =  display.show().await;
+                if showing_readings(&menu).await {
+                    let status = status_message().await;
+                    show_home(&mut display, false, false, status.as_str(), &profile, &usb_sense).await;
                 }
             }
 
             let time_diff = now - *LAST_UPDATE.lock().await;
             info!("Here");     // Debug colsole
             // Check if it is time to get a new temperature reading
-            if time_diff > check_seconds {
+            if time_diff > check_seconds && !reading_pending {
                 info!("getting new reading");     // Debug colsole
-                let _ = get_current_temp(&mut temp_sensor).await;    // Get a temperature reading
+                TEMP_REQUEST.signal(());    // The sensor task runs the conversion without blocking this loop
+                reading_pending = true;
+            }
+
+            // Act on the reading once the sensor task has finished it
+            if reading_pending && TEMP_READY.try_take().is_some() {
+                reading_pending = false;
+                let time_diff = Instant::now().as_secs() - *LAST_UPDATE.lock().await;
 
                 if *NO_DEVICE.lock().await {
-                    if *DISPLAY_ON.lock().await {
-                       let _ = display.clear_all().await;
-                       let _ = display.refresh_line_4("Sensor not found").await;
-                       let _ = display.show().await;
+                    if *DISPLAY_ON.lock().await && !menu.is_open() {
+                        show_home(&mut display, false, false, sensor_fault_message().await, &profile, &usb_sense).await;
                     }
                 }
                 else {
                     // Display the latest readings
-                    if *DISPLAY_ON.lock().await {
-                        let cur_tmp = f32_to_string(*CURRENT_TEMP.lock().await);
-                        let tar_tmp = f32_to_string(*TARGET_TEMP.lock().await);
-                        let cur_var = f32_to_string(*CURRENT_VARIANCE.lock().await);
-                        let msg = "";
-                        let _ = display.refresh_readings(cur_tmp.as_str(), tar_tmp.as_str(), cur_var.as_str(), msg).await;
+                    if showing_readings(&menu).await {
+                        let msg = status_message().await;
+                        show_home(&mut display, heating_relay.is_set_high(), cooling_relay.is_set_high(), msg.as_str(), &profile, &usb_sense).await;
                     }
                     info!("Then here");     // Debug colsole
-                    if (*CURRENT_VARIANCE.lock().await).abs() > TOLERANCE {
+                    if let Some(fault) = fail_safe_message().await {
+                        // Fail safe: leave both relays off until the alarm clears
+                        heating_relay.set_low();
+                        cooling_relay.set_low();
+                        *RELAY_ON.lock().await = false;
+                        if showing_readings(&menu).await {
+                            show_home(&mut display, false, false, fault, &profile, &usb_sense).await;
+                        }
+                    }
+                    else if (*CURRENT_VARIANCE.lock().await).abs() > TOLERANCE {
                         let integral = ((time_diff as f32) * *CURRENT_VARIANCE.lock().await) + *INTEGRAL.lock().await;
                         //*INTEGRAL.lock().await = *INTEGRAL.lock().await + time_diff as f32 * *CURRENT_VARIANCE.lock().await;
                         *INTEGRAL.lock().await = integral;
@@ -318,9 +1376,8 @@ async fn main(_spawner: Spawner) {
 
                         if out > 0 {
                             info!("Heating on");     // Debug colsole
-                            if *DISPLAY_ON.lock().await {
-                                let _ = display.refresh_line_4("   HEATING ON   ").await;
-                                let _ = display.show().await;
+                            if showing_readings(&menu).await {
+                                show_home(&mut display, true, false, "   HEATING ON   ", &profile, &usb_sense).await;
                             }
                             *RELAY_ON.lock().await = true;
                             *SWITCH_OFF_RELAYS.lock().await = Instant::now().as_secs() + out.abs() as u64;
@@ -329,9 +1386,8 @@ async fn main(_spawner: Spawner) {
 
                         if out < 0 {
                             info!("Cooling on");     // Debug colsole
-                            if *DISPLAY_ON.lock().await {
-                                let _ = display.refresh_line_4("   COOLING ON   ").await;
-                                let _ = display.show().await;
+                            if showing_readings(&menu).await {
+                                show_home(&mut display, false, true, "   COOLING ON   ", &profile, &usb_sense).await;
                             }
                             *RELAY_ON.lock().await = true;
                             *SWITCH_OFF_RELAYS.lock().await = Instant::now().as_secs() + out.abs() as u64;
@@ -347,6 +1403,25 @@ async fn main(_spawner: Spawner) {
              
 
         }
+        // Record the history for the graph, noting whether each relay was on at any point in the interval
+        history_sample.heating |= heating_relay.is_set_high();
+        history_sample.cooling |= cooling_relay.is_set_high();
+        let now = Instant::now().as_secs();
+        if now >= next_history && !*NO_DEVICE.lock().await {
+            history_sample.temp = *CURRENT_TEMP.lock().await;
+            history_sample.target = *TARGET_TEMP.lock().await;
+            HISTORY.lock().await.push(history_sample);
+            history_sample = HistorySample::default();
+            next_history = now + HISTORY_INTERVAL;
+            if *DISPLAY_ON.lock().await && *SHOW_GRAPH.lock().await && !menu.is_open() {
+                let _ = display.show_graph(&*HISTORY.lock().await, HISTORY_HOURS).await;
+            }
+        }
+
+        // Mirror the relays onto any 1-Wire switches, with the fan running while either relay is on
+        set_output(OutputRole::Heater, heating_relay.is_set_high()).await;
+        set_output(OutputRole::Cooler, cooling_relay.is_set_high()).await;
+        set_output(OutputRole::Fan, heating_relay.is_set_high() || cooling_relay.is_set_high()).await;
         delay.delay_ms(500).await;
 
     }
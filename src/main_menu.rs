@@ -0,0 +1,45 @@
+use crate::menu::{Entry, EntryKind};
+use crate::oled::Rotation;
+use crate::temperature::TemperatureUnit;
+
+pub const MENU_ENTRIES: usize = 9;
+pub const MENU_TARGET: usize = 2;   // Entries the firmware acts on when they change
+pub const MENU_ROTATE: usize = 3;
+pub const MENU_MIRROR: usize = 4;
+pub const MENU_UNIT: usize = 5;
+pub const MENU_TARE: usize = 6;
+pub const MENU_CALIBRATE: usize = 7;
+pub const MENU_PITCHED: usize = 8;
+
+/// Screens that can be opened from the menu
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Screen {
+    Readings,
+    Graph,
+}
+
+/// Rotations offered in the menu, quarter turns only when the panel is square
+pub const fn rotations(square: bool) -> &'static [Rotation] {
+    if square { &[Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270] } else { &[Rotation::Deg0, Rotation::Deg180] }
+}
+
+/// Labels for `rotations`
+pub const fn rotation_labels(square: bool) -> &'static [&'static str] {
+    if square { &["0", "90", "180", "270"] } else { &["0", "180"] }
+}
+
+/// The controller's menu, for a square panel or not.
+/// The target range is given in degrees C, the firmware sets the range and step for the unit in use with `Menu::set_kind`.
+pub const fn main_menu(square: bool, min_temp: f32, max_temp: f32, unit: TemperatureUnit) -> [Entry<Screen>; MENU_ENTRIES] {
+    [
+        Entry { label: "Readings", kind: EntryKind::Screen(Screen::Readings) },
+        Entry { label: "Graph", kind: EntryKind::Screen(Screen::Graph) },
+        Entry { label: "Target", kind: EntryKind::Number { min: min_temp, max: max_temp, step: unit.step() } },
+        Entry { label: "Rotate", kind: EntryKind::Choice(rotation_labels(square)) },
+        Entry { label: "Mirror", kind: EntryKind::Choice(&["Off", "On"]) },
+        Entry { label: "Unit", kind: EntryKind::Choice(&["C", "F"]) },     // In the order of `TemperatureUnit::ALL`
+        Entry { label: "Tare", kind: EntryKind::Action },     // Zero the scale with nothing on it
+        Entry { label: "Calib g", kind: EntryKind::Number { min: 0.0, max: 10000.0, step: 100.0 } },     // Known mass put on the tared scale
+        Entry { label: "Pitched", kind: EntryKind::Action },  // Measure CO2 loss from the fermenter's weight now
+    ]
+}
@@ -0,0 +1,200 @@
+use embassy_time::Timer;
+use embedded_hal_async::spi::SpiDevice;
+#[allow(unused_imports)]   // The host tests link std, which has these maths functions built in
+use micromath::F32Ext;
+
+use crate::sensor::SensorError;
+use crate::temperature::TemperatureSensor;
+
+// Callendar–Van Dusen coefficients for platinum RTDs (IEC 60751)
+const RTD_A: f32 = 3.9083e-3;
+const RTD_B: f32 = -5.775e-7;
+const RTD_C: f32 = -4.183e-12;  // Only used below 0 degrees C
+
+// Registers
+const REG_CONFIG: u8 = 0x00;
+const REG_RTD_MSB: u8 = 0x01;
+const REG_HIGH_FAULT_MSB: u8 = 0x03;    // Followed by the high LSB and the low fault threshold MSB and LSB
+const REG_FAULT_STATUS: u8 = 0x07;
+const WRITE: u8 = 0x80;         // Set on the register address for writes
+
+// Configuration register bits
+const CONFIG_VBIAS: u8 = 0x80;
+const CONFIG_AUTO: u8 = 0x40;   // Continuous conversions
+const CONFIG_3WIRE: u8 = 0x10;
+const CONFIG_FAULT_DETECT: u8 = 0x04;   // Run a fault detection cycle with automatic delay
+const CONFIG_FAULT_CYCLE: u8 = 0x0C;    // Fault detection cycle bits, read back as 0 when the cycle has finished
+const CONFIG_FAULT_CLEAR: u8 = 0x02;
+const CONFIG_FILTER_50HZ: u8 = 0x01;
+
+const FAULT_CYCLE_POLLS: u8 = 10;       // Polls 100us apart before a fault detection cycle is treated as stuck (it takes about 600us)
+
+/// Platinum RTD element connected to the MAX31865
+#[derive(Copy, Clone, PartialEq)]
+pub enum RtdType {
+    Pt100,
+    Pt1000,
+}
+
+impl RtdType {
+    /// Resistance at 0 degrees C (ohms)
+    pub fn nominal_resistance(&self) -> f32 {
+        match self {
+            RtdType::Pt100 => 100.0,
+            RtdType::Pt1000 => 1000.0,
+        }
+    }
+}
+
+/// Number of wires between the RTD and the MAX31865
+#[derive(Copy, Clone, PartialEq)]
+pub enum RtdWires {
+    Two,
+    Three,
+    Four,
+}
+
+/// Settings for the MAX31865 RTD converter
+#[derive(Copy, Clone)]
+pub struct Max31865Config {
+    pub rtd: RtdType,
+    pub wires: RtdWires,
+    pub reference_resistor: f32,    // Reference resistor fitted to the board (430 ohms for PT100, 4300 ohms for PT1000)
+    pub filter_50hz: bool,          // Reject 50Hz mains noise instead of 60Hz
+    pub fault_low: f32,             // Readings below this are flagged as a fault by the converter (degrees C)
+    pub fault_high: f32,            // Readings above this are flagged as a fault by the converter (degrees C)
+}
+
+/// MAX31865 PT100/PT1000 RTD to digital converter on SPI
+pub struct Max31865<SPI> {
+    spi: SPI,
+    config: Max31865Config,
+}
+
+impl<SPI> Max31865<SPI>
+where
+    SPI: SpiDevice,
+{
+    pub fn new(spi: SPI, config: Max31865Config) -> Self {
+        Self { spi, config }
+    }
+
+    /// Program the fault thresholds, check the wiring with a fault detection cycle, then turn on the bias voltage and
+    /// start continuous conversions. Conversions are started even if the wiring check finds a fault, which is returned.
+    pub async fn init(&mut self) -> Result<(), SensorError> {
+        let high = self.threshold(self.config.fault_high).to_be_bytes();
+        let low = self.threshold(self.config.fault_low).to_be_bytes();
+        self.spi.write(&[REG_HIGH_FAULT_MSB | WRITE, high[0], high[1], low[0], low[1]]).await.map_err(|_| SensorError::BusError)?;
+        let wiring = self.detect_faults().await;
+        self.write_register(REG_CONFIG, self.config_byte() | CONFIG_FAULT_CLEAR).await?;
+        wiring
+    }
+
+    /// Run the automatic fault detection cycle, which checks the RTD and reference inputs for open and shorted wiring
+    async fn detect_faults(&mut self) -> Result<(), SensorError> {
+        let config = self.config_byte() & !CONFIG_AUTO;     // Conversions have to be stopped during the cycle
+        self.write_register(REG_CONFIG, config | CONFIG_FAULT_CLEAR).await?;
+        self.write_register(REG_CONFIG, config | CONFIG_FAULT_DETECT).await?;
+        for _ in 0..FAULT_CYCLE_POLLS {
+            Timer::after_micros(100).await;
+            if self.read_register(REG_CONFIG).await? & CONFIG_FAULT_CYCLE == 0 {
+                return match self.read_register(REG_FAULT_STATUS).await? {
+                    0 => Ok(()),
+                    status => Err(Self::decode_fault(status)),
+                };
+            }
+        }
+        Err(SensorError::BusError)
+    }
+
+    /// Fault threshold register value for a temperature: the ratio of the RTD to the reference resistor, left aligned
+    fn threshold(&self, temp: f32) -> u16 {
+        let ratio = self.temperature_to_resistance(temp) / self.config.reference_resistor;
+        ((ratio * 32768.0) as u16).min(0x7FFF) << 1
+    }
+
+    /// RTD resistance in ohms
+    pub async fn resistance(&mut self) -> Result<f32, SensorError> {
+        let mut data = [REG_RTD_MSB, 0, 0];
+        self.spi.transfer_in_place(&mut data).await.map_err(|_| SensorError::BusError)?;
+        if data[2] & 0x01 != 0 {
+            return Err(self.read_fault().await);
+        }
+        let raw = (u16::from_be_bytes([data[1], data[2]]) >> 1) as f32;
+        Ok(raw * self.config.reference_resistor / 32768.0)
+    }
+
+    /// Read and clear the fault status register
+    async fn read_fault(&mut self) -> SensorError {
+        let status = match self.read_register(REG_FAULT_STATUS).await {
+            Ok(status) => status,
+            Err(fault) => return fault,
+        };
+        let _ = self.write_register(REG_CONFIG, self.config_byte() | CONFIG_FAULT_CLEAR).await;
+        Self::decode_fault(status)
+    }
+
+    fn decode_fault(status: u8) -> SensorError {
+        match status {
+            status if status & 0x40 != 0 => SensorError::ShortCircuit,  // RTD below the low threshold
+            status if status & 0x04 != 0 => SensorError::ShortCircuit,  // Over or under voltage on an input
+            _ => SensorError::OpenCircuit,      // RTD above the high threshold or a FORCE line is open
+        }
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, SensorError> {
+        let mut data = [register, 0];
+        self.spi.transfer_in_place(&mut data).await.map_err(|_| SensorError::BusError)?;
+        Ok(data[1])
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), SensorError> {
+        self.spi.write(&[register | WRITE, value]).await.map_err(|_| SensorError::BusError)
+    }
+
+    fn config_byte(&self) -> u8 {
+        let mut config = CONFIG_VBIAS | CONFIG_AUTO;
+        if self.config.wires == RtdWires::Three {
+            config |= CONFIG_3WIRE;
+        }
+        if self.config.filter_50hz {
+            config |= CONFIG_FILTER_50HZ;
+        }
+        config
+    }
+
+    /// Convert degrees C to an RTD resistance (Callendar–Van Dusen)
+    fn temperature_to_resistance(&self, temp: f32) -> f32 {
+        let c = if temp < 0.0 { RTD_C * (temp - 100.0) * temp * temp * temp } else { 0.0 };
+        self.config.rtd.nominal_resistance() * (1.0 + RTD_A * temp + RTD_B * temp * temp + c)
+    }
+
+    /// Convert an RTD resistance to degrees C
+    fn resistance_to_temperature(&self, resistance: f32) -> f32 {
+        let r0 = self.config.rtd.nominal_resistance();
+        // Callendar–Van Dusen solved for T, exact at and above 0 degrees C
+        let temp = (-RTD_A + (RTD_A * RTD_A - 4.0 * RTD_B * (1.0 - resistance / r0)).sqrt()) / (2.0 * RTD_B);
+        if temp >= 0.0 {
+            return temp;
+        }
+        // Below 0 degrees C use a polynomial fit, scaled to a PT100
+        let r = resistance / r0 * 100.0;
+        let mut power = r;
+        let mut temp = -242.02;
+        for coefficient in [2.2228, 2.5859e-3, -4.8260e-6, -2.8183e-8, 1.5243e-10] {
+            temp += coefficient * power;
+            power *= r;
+        }
+        temp
+    }
+}
+
+impl<SPI> TemperatureSensor for Max31865<SPI>
+where
+    SPI: SpiDevice,
+{
+    async fn read_temperature(&mut self) -> Result<f32, SensorError> {
+        let resistance = self.resistance().await?;
+        Ok(self.resistance_to_temperature(resistance))
+    }
+}
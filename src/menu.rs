@@ -0,0 +1,430 @@
+use core::fmt::Write;
+use heapless::{String, Vec};
+
+pub const MENU_LINES: usize = 4;    // Entries shown at once, one per display line
+pub const MAX_ENTRIES: usize = 16;
+const LABEL_WIDTH: usize = 8;       // Labels are padded to this so the values line up
+
+/// What the two keys mean to the menu.
+/// Key0 is Next (short press) / Select (long press) and key1 is Previous (short press) / Back (long press).
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum Key {
+    Next,       // Move down the list, or increase the value being edited
+    Previous,   // Move up the list, or decrease the value being edited
+    Select,     // Open the menu, open a screen, start editing or keep the edited value
+    Back,       // Close the menu, or throw away the edited value
+}
+
+/// What a menu entry does when it is selected
+#[derive(Copy, Clone)]
+pub enum EntryKind<S> {
+    Screen(S),                                      // Close the menu and show a screen
+    Number { min: f32, max: f32, step: f32 },       // A number shown to one decimal place
+    Choice(&'static [&'static str]),                // One of a list of options
+    Action,                                         // Something the caller does straight away, the menu stays open
+}
+
+#[derive(Copy, Clone)]
+pub struct Entry<S> {
+    pub label: &'static str,    // Up to 8 characters
+    pub kind: EntryKind<S>,
+}
+
+/// The value of an entry
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum Value {
+    None,           // Screen and action entries have no value
+    Number(f32),
+    Choice(usize),  // Index into the options
+}
+
+/// What the caller needs to act on after a key press
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuEvent<S> {
+    None,
+    Changed(usize, Value),  // An entry was edited and its new value kept
+    Open(S),                // A screen was selected and the menu closed
+    Action(usize),          // An action entry was selected
+    Closed,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    Closed,
+    Browsing,
+    Editing(Value),     // Holds the value from before the edit so Back can restore it
+}
+
+/// A list of screens and editable settings navigated with two keys
+pub struct Menu<S> {
+    entries: Vec<Entry<S>, MAX_ENTRIES>,
+    values: Vec<Value, MAX_ENTRIES>,
+    locked: u16,        // One bit per entry that can't be edited at the moment
+    cursor: usize,
+    state: State,
+}
+
+impl<S: Copy> Menu<S> {
+    /// Numbers start at their minimum and choices at their first option until set with `set_value`
+    pub fn new(entries: &[Entry<S>]) -> Self {
+        let entries: Vec<Entry<S>, MAX_ENTRIES> = entries.iter().take(MAX_ENTRIES).copied().collect();
+        let values = entries.iter().map(|entry| match entry.kind {
+            EntryKind::Screen(_) | EntryKind::Action => Value::None,
+            EntryKind::Number { min, .. } => Value::Number(min),
+            EntryKind::Choice(_) => Value::Choice(0),
+        }).collect();
+        Self { entries, values, locked: 0, cursor: 0, state: State::Closed }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state != State::Closed
+    }
+
+    /// Open at the first entry. A menu with no entries stays closed.
+    pub fn open(&mut self) {
+        if self.values.is_empty() {
+            return;
+        }
+        self.cursor = 0;
+        self.state = State::Browsing;
+    }
+
+    /// Close without keeping an edit in progress
+    pub fn close(&mut self) {
+        if let State::Editing(original) = self.state {
+            self.values[self.cursor] = original;
+        }
+        self.state = State::Closed;
+    }
+
+    pub fn value(&self, index: usize) -> Value {
+        self.values.get(index).copied().unwrap_or(Value::None)
+    }
+
+    /// Set an entry's value, e.g. from a setting that was changed outside the menu
+    pub fn set_value(&mut self, index: usize, value: Value) {
+        if let Some(slot) = self.values.get_mut(index) {
+            *slot = value;
+        }
+    }
+
+    /// Change what an entry edits, e.g. the range of a number when its unit changes.
+    /// The caller sets the value to suit with `set_value`.
+    pub fn set_kind(&mut self, index: usize, kind: EntryKind<S>) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.kind = kind;
+        }
+    }
+
+    /// Stop an entry being edited, e.g. while the reading it depends on is missing. An edit of it in progress is thrown away.
+    pub fn set_locked(&mut self, index: usize, locked: bool) {
+        if index >= MAX_ENTRIES {
+            return;
+        }
+        if locked {
+            self.locked |= 1 << index;
+            if let (State::Editing(original), true) = (self.state, index == self.cursor) {
+                self.values[index] = original;
+                self.state = State::Browsing;
+            }
+        } else {
+            self.locked &= !(1 << index);
+        }
+    }
+
+    pub fn handle(&mut self, key: Key) -> MenuEvent<S> {
+        let count = self.values.len();
+        match self.state {
+            State::Closed => {
+                if key == Key::Select {
+                    self.open();
+                }
+                MenuEvent::None
+            }
+            State::Browsing => match key {
+                Key::Next => {
+                    self.cursor = (self.cursor + 1) % count;
+                    MenuEvent::None
+                }
+                Key::Previous => {
+                    self.cursor = (self.cursor + count - 1) % count;
+                    MenuEvent::None
+                }
+                Key::Back => {
+                    self.state = State::Closed;
+                    MenuEvent::Closed
+                }
+                Key::Select => match self.entries[self.cursor].kind {
+                    EntryKind::Screen(screen) => {
+                        self.state = State::Closed;
+                        MenuEvent::Open(screen)
+                    }
+                    _ if self.locked & (1 << self.cursor) != 0 => MenuEvent::None,
+                    EntryKind::Action => MenuEvent::Action(self.cursor),
+                    _ => {
+                        self.state = State::Editing(self.values[self.cursor]);
+                        MenuEvent::None
+                    }
+                },
+            },
+            State::Editing(original) => match key {
+                Key::Next | Key::Previous => {
+                    self.values[self.cursor] = self.step(self.values[self.cursor], key == Key::Next);
+                    MenuEvent::None
+                }
+                Key::Select => {
+                    self.state = State::Browsing;
+                    let value = self.values[self.cursor];
+                    if value == original {
+                        MenuEvent::None
+                    } else {
+                        MenuEvent::Changed(self.cursor, value)
+                    }
+                }
+                Key::Back => {
+                    self.values[self.cursor] = original;
+                    self.state = State::Browsing;
+                    MenuEvent::None
+                }
+            },
+        }
+    }
+
+    /// Numbers stop at their limits and choices wrap around
+    fn step(&self, value: Value, up: bool) -> Value {
+        match (self.entries[self.cursor].kind, value) {
+            (EntryKind::Number { min, max, step }, Value::Number(number)) => {
+                let number = if up { number + step } else { number - step };
+                Value::Number(number.clamp(min, max))
+            }
+            (EntryKind::Choice(options), Value::Choice(index)) => {
+                let count = options.len().max(1);
+                Value::Choice(if up { (index + 1) % count } else { (index + count - 1) % count })
+            }
+            _ => value,
+        }
+    }
+
+    /// The text for each display line. The entry under the cursor is marked with `>`, or `*` while it is being edited.
+    pub fn render(&self) -> [String<16>; MENU_LINES] {
+        let mut lines: [String<16>; MENU_LINES] = Default::default();
+        let first = (self.cursor + 1).saturating_sub(MENU_LINES);  // Scroll to keep the cursor on screen
+        for (line, index) in lines.iter_mut().zip(first..self.values.len()) {
+            let marker = match self.state {
+                State::Editing(_) if index == self.cursor => '*',
+                _ if index == self.cursor => '>',
+                _ => ' ',
+            };
+            let _ = write!(line, "{}{:<width$}", marker, self.entries[index].label, width = LABEL_WIDTH);
+            let _ = match (self.entries[index].kind, self.values[index]) {
+                (EntryKind::Number { .. }, Value::Number(number)) => write!(line, "{:.1}", number),
+                (EntryKind::Choice(options), Value::Choice(choice)) => write!(line, "{}", options.get(choice).unwrap_or(&"?")),
+                _ => Ok(()),
+            };
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    enum TestScreen {
+        Readings,
+        Graph,
+    }
+
+    const ENTRIES: &[Entry<TestScreen>] = &[
+        Entry { label: "Readings", kind: EntryKind::Screen(TestScreen::Readings) },
+        Entry { label: "Graph", kind: EntryKind::Screen(TestScreen::Graph) },
+        Entry { label: "Target", kind: EntryKind::Number { min: 0.0, max: 1.0, step: 0.5 } },
+        Entry { label: "Mirror", kind: EntryKind::Choice(&["Off", "On"]) },
+        Entry { label: "Unit", kind: EntryKind::Choice(&["C", "F", "K"]) },
+    ];
+    const TARGET: usize = 2;
+    const MIRROR: usize = 3;
+    const UNIT: usize = 4;
+
+    /// An open menu with the cursor on `index`
+    fn menu_at(index: usize) -> Menu<TestScreen> {
+        let mut menu = Menu::new(ENTRIES);
+        menu.open();
+        for _ in 0..index {
+            menu.handle(Key::Next);
+        }
+        menu
+    }
+
+    fn lines(menu: &Menu<TestScreen>) -> [std::string::String; MENU_LINES] {
+        menu.render().map(|line| line.as_str().into())
+    }
+
+    #[test]
+    fn select_opens_the_menu_at_the_first_entry() {
+        let mut menu = Menu::new(ENTRIES);
+        assert!(!menu.is_open());
+        assert_eq!(menu.handle(Key::Next), MenuEvent::None);
+        assert!(!menu.is_open());
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+        assert!(menu.is_open());
+        assert!(menu.render()[0].starts_with(">Readings"));
+    }
+
+    #[test]
+    fn cursor_wraps_around_both_ends() {
+        let mut menu = menu_at(0);
+        menu.handle(Key::Previous);
+        assert_eq!(menu.cursor, ENTRIES.len() - 1);
+        menu.handle(Key::Next);
+        assert_eq!(menu.cursor, 0);
+    }
+
+    #[test]
+    fn choices_wrap_around_both_ends() {
+        let mut menu = menu_at(UNIT);
+        menu.handle(Key::Select);
+        menu.handle(Key::Previous);
+        assert_eq!(menu.value(UNIT), Value::Choice(2));
+        menu.handle(Key::Next);
+        assert_eq!(menu.value(UNIT), Value::Choice(0));
+    }
+
+    #[test]
+    fn numbers_are_clamped_to_their_limits() {
+        let mut menu = menu_at(TARGET);
+        menu.handle(Key::Select);
+        menu.handle(Key::Previous);
+        assert_eq!(menu.value(TARGET), Value::Number(0.0));
+        for _ in 0..5 {
+            menu.handle(Key::Next);
+        }
+        assert_eq!(menu.value(TARGET), Value::Number(1.0));
+    }
+
+    #[test]
+    fn back_restores_the_value_from_before_the_edit() {
+        let mut menu = menu_at(MIRROR);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        assert_eq!(menu.value(MIRROR), Value::Choice(1));
+        assert_eq!(menu.handle(Key::Back), MenuEvent::None);
+        assert_eq!(menu.value(MIRROR), Value::Choice(0));
+        assert!(menu.is_open());
+    }
+
+    #[test]
+    fn closing_restores_the_value_from_before_the_edit() {
+        let mut menu = menu_at(MIRROR);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        menu.close();
+        assert_eq!(menu.value(MIRROR), Value::Choice(0));
+    }
+
+    #[test]
+    fn changed_only_fires_when_the_value_changed() {
+        let mut menu = menu_at(TARGET);
+        menu.handle(Key::Select);
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        menu.handle(Key::Previous);
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        assert_eq!(menu.handle(Key::Select), MenuEvent::Changed(TARGET, Value::Number(0.5)));
+    }
+
+    #[test]
+    fn screens_close_the_menu() {
+        let mut menu = menu_at(1);
+        assert_eq!(menu.handle(Key::Select), MenuEvent::Open(TestScreen::Graph));
+        assert!(!menu.is_open());
+        let mut menu = menu_at(0);
+        assert_eq!(menu.handle(Key::Back), MenuEvent::Closed);
+        assert!(!menu.is_open());
+    }
+
+    #[test]
+    fn actions_are_reported_and_the_menu_stays_open() {
+        let mut menu: Menu<TestScreen> = Menu::new(&[Entry { label: "Tare", kind: EntryKind::Action }]);
+        menu.open();
+        assert_eq!(menu.handle(Key::Select), MenuEvent::Action(0));
+        assert!(menu.is_open());
+        assert_eq!(lines(&menu)[0], ">Tare    ");
+        menu.set_locked(0, true);
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+    }
+
+    #[test]
+    fn menu_without_entries_stays_closed() {
+        let mut menu: Menu<TestScreen> = Menu::new(&[]);
+        menu.open();
+        assert!(!menu.is_open());
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+        assert_eq!(menu.handle(Key::Next), MenuEvent::None);
+        assert!(!menu.is_open());
+    }
+
+    #[test]
+    fn locked_entries_are_not_edited() {
+        let mut menu = menu_at(TARGET);
+        menu.set_locked(TARGET, true);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        assert_eq!(menu.value(TARGET), Value::Number(0.0));
+        assert_eq!(menu.cursor, MIRROR);
+        menu.set_locked(TARGET, false);
+        menu.handle(Key::Previous);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        assert_eq!(menu.value(TARGET), Value::Number(0.5));
+    }
+
+    #[test]
+    fn locking_throws_away_an_edit_in_progress() {
+        let mut menu = menu_at(TARGET);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        menu.set_locked(TARGET, true);
+        assert_eq!(menu.value(TARGET), Value::Number(0.0));
+        assert_eq!(menu.handle(Key::Select), MenuEvent::None);
+        assert!(menu.render()[2].starts_with(">Target"));
+    }
+
+    #[test]
+    fn numbers_follow_a_new_range() {
+        let mut menu = menu_at(TARGET);
+        menu.set_kind(TARGET, EntryKind::Number { min: 10.0, max: 20.0, step: 2.0 });
+        menu.set_value(TARGET, Value::Number(18.0));
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        menu.handle(Key::Next);
+        assert_eq!(menu.value(TARGET), Value::Number(20.0));
+    }
+
+    #[test]
+    fn render_marks_the_cursor_and_the_entry_being_edited() {
+        let mut menu = menu_at(TARGET);
+        assert_eq!(lines(&menu), [" Readings", " Graph   ", ">Target  0.0", " Mirror  Off"]);
+        menu.handle(Key::Select);
+        menu.handle(Key::Next);
+        assert_eq!(lines(&menu), [" Readings", " Graph   ", "*Target  0.5", " Mirror  Off"]);
+    }
+
+    #[test]
+    fn render_scrolls_to_keep_the_cursor_on_screen() {
+        let mut menu = menu_at(UNIT);
+        assert_eq!(lines(&menu), [" Graph   ", " Target  0.0", " Mirror  Off", ">Unit    C"]);
+        menu.handle(Key::Next);
+        assert_eq!(lines(&menu), [">Readings", " Graph   ", " Target  0.0", " Mirror  Off"]);
+    }
+
+    #[test]
+    fn render_leaves_spare_lines_blank() {
+        let menu = Menu::new(&ENTRIES[..2]);
+        assert_eq!(lines(&menu), [">Readings", " Graph   ", "", ""]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+use embedded_hal::digital::{self, OutputPin};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use embedded_hal_async::spi::{self, SpiDevice};
+
+use crate::onewire::{OneWire, Triplet};
+use crate::sensor::SensorError;
+
+/// I2C bus for driver tests. Writes are recorded with their address and reads are answered in order from a queue.
+/// A read with nothing left to answer it is NACKed, like a missing device.
+#[derive(Default)]
+pub struct MockI2c {
+    pub writes: Vec<(u8, Vec<u8>)>,
+    pub absent: bool,       // NACK writes too, like a device that isn't fitted
+    reads: VecDeque<Vec<u8>>,
+}
+
+impl MockI2c {
+    pub fn new(reads: &[&[u8]]) -> Self {
+        Self { reads: reads.iter().map(|read| read.to_vec()).collect(), ..Self::default() }
+    }
+}
+
+impl ErrorType for MockI2c {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2c {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                Operation::Write(_) if self.absent => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+                Operation::Write(bytes) => self.writes.push((address, bytes.to_vec())),
+                Operation::Read(buffer) => {
+                    let read = self.reads.pop_front().ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+                    buffer.copy_from_slice(&read[..buffer.len()]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SPI device for driver tests. Reads are answered in order from a queue, and a read with nothing left fails.
+#[derive(Default)]
+pub struct MockSpi {
+    reads: VecDeque<Vec<u8>>,
+}
+
+impl MockSpi {
+    pub fn new(reads: &[&[u8]]) -> Self {
+        Self { reads: reads.iter().map(|read| read.to_vec()).collect() }
+    }
+}
+
+impl spi::ErrorType for MockSpi {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice for MockSpi {
+    async fn transaction(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<(), Self::Error> {
+        for operation in operations {
+            if let spi::Operation::Read(buffer) = operation {
+                let read = self.reads.pop_front().ok_or(spi::ErrorKind::Other)?;
+                buffer.copy_from_slice(&read[..buffer.len()]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delay that returns straight away and adds up how long it was asked to wait
+#[derive(Default)]
+pub struct MockDelay {
+    pub total_ns: u64,
+}
+
+impl DelayNs for MockDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.total_ns += ns as u64;
+    }
+}
+
+/// 1-Wire bus with devices that take part in ROM searches like real ones: the bits read are the wired-AND of every
+/// device still in the search, and devices drop out when the direction written doesn't match their bit.
+#[derive(Default)]
+pub struct MockOneWire {
+    pub roms: Vec<[u8; 8]>,         // Devices on the bus
+    pub alarmed: Vec<[u8; 8]>,      // Devices that answer an Alarm Search
+    pub unplug_after: Option<usize>,    // Every device stops answering after this many search triplets
+    pub writes: Vec<Vec<u8>>,
+    pub reads: VecDeque<Vec<u8>>,   // Answers to reads in order, an idle bus (all 1s) once they run out
+    active: Vec<[u8; 8]>,
+    bit: usize,
+    triplets: usize,
+}
+
+impl MockOneWire {
+    pub fn new(roms: &[[u8; 8]]) -> Self {
+        Self { roms: roms.to_vec(), ..Self::default() }
+    }
+}
+
+impl OneWire for MockOneWire {
+    async fn reset(&mut self) -> Result<bool, SensorError> {
+        self.active.clear();
+        self.bit = 0;
+        Ok(!self.roms.is_empty())
+    }
+
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SensorError> {
+        self.active = match bytes.first() {
+            Some(0xF0) => self.roms.clone(),
+            Some(0xEC) => self.alarmed.clone(),
+            _ => Vec::new(),
+        };
+        self.writes.push(bytes.to_vec());
+        Ok(())
+    }
+
+    async fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SensorError> {
+        match self.reads.pop_front() {
+            Some(read) => buffer.copy_from_slice(&read[..buffer.len()]),
+            None => buffer.fill(0xFF),
+        }
+        Ok(())
+    }
+
+    async fn triplet(&mut self, direction: bool) -> Result<Triplet, SensorError> {
+        self.triplets += 1;
+        if self.unplug_after.is_some_and(|after| self.triplets > after) {
+            self.active.clear();
+        }
+        let bit = |rom: &[u8; 8]| rom[self.bit / 8] & (1 << (self.bit % 8)) != 0;
+        let id_bit = self.active.iter().all(bit);
+        let complement_bit = self.active.iter().all(|rom| !bit(rom));
+        let direction = match (id_bit, complement_bit) {
+            (false, false) => direction,
+            (id_bit, _) => id_bit,
+        };
+        self.active.retain(|rom| bit(rom) == direction);
+        self.bit += 1;
+        Ok(Triplet { id_bit, complement_bit, direction })
+    }
+}
+
+/// Output pin that remembers the level it was last set to
+#[derive(Default)]
+pub struct MockPin {
+    pub high: bool,
+}
+
+impl digital::ErrorType for MockPin {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high = true;
+        Ok(())
+    }
+}
+
+/// Display bus that accepts whatever is sent to it, so screens can be drawn into the framebuffer on the host
+#[derive(Default)]
+pub struct MockInterface {
+    pub absent: bool,       // Every write fails, like a display that isn't connected
+}
+
+impl AsyncWriteOnlyDataCommand for MockInterface {
+    async fn send_commands(&mut self, _commands: DataFormat<'_>) -> Result<(), DisplayError> {
+        match self.absent {
+            true => Err(DisplayError::BusWriteError),
+            false => Ok(()),
+        }
+    }
+
+    async fn send_data(&mut self, _data: DataFormat<'_>) -> Result<(), DisplayError> {
+        match self.absent {
+            true => Err(DisplayError::BusWriteError),
+            false => Ok(()),
+        }
+    }
+}
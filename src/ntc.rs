@@ -0,0 +1,66 @@
+use embassy_rp::adc::Channel;
+use micromath::F32Ext;
+
+use crate::analog::{read_average, SharedAdc, ADC_MAX};
+use crate::sensor::SensorError;
+use crate::temperature::TemperatureSensor;
+
+const KELVIN: f32 = 273.15;
+const RAIL_MARGIN: u16 = 40;    // Readings this close to either rail (about 1%) are a short or open circuit, not a temperature
+
+/// Steinhart–Hart coefficients for a thermistor: 1/T = A + B ln(R) + C ln(R)^3
+#[derive(Copy, Clone)]
+pub struct SteinhartHart {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+impl SteinhartHart {
+    /// Typical coefficients for a 10k NTC thermowell probe
+    pub const NTC_10K: Self = Self { a: 1.009_249_5e-3, b: 2.378_405_4e-4, c: 2.019_202_7e-7 };
+
+    /// Temperature in degrees C for a thermistor resistance in ohms
+    pub fn temperature(&self, resistance: f32) -> f32 {
+        let ln_r = resistance.ln();
+        1.0 / (self.a + self.b * ln_r + self.c * ln_r * ln_r * ln_r) - KELVIN
+    }
+}
+
+/// Settings for an NTC thermistor wired as the lower half of a divider (3V3 - series resistor - ADC pin - thermistor - GND)
+#[derive(Copy, Clone)]
+pub struct NtcConfig {
+    pub series_resistor: f32,       // Resistor between 3V3 and the ADC pin (ohms)
+    pub coefficients: SteinhartHart,
+    pub samples: u8,                // Number of ADC samples averaged for each reading
+}
+
+/// NTC thermistor read through the RP2040 ADC
+pub struct NtcThermistor<'d> {
+    adc: &'d SharedAdc<'d>,
+    channel: Channel<'d>,
+    config: NtcConfig,
+}
+
+impl<'d> NtcThermistor<'d> {
+    pub fn new(adc: &'d SharedAdc<'d>, channel: Channel<'d>, config: NtcConfig) -> Self {
+        Self { adc, channel, config }
+    }
+
+    /// Thermistor resistance in ohms
+    pub async fn resistance(&mut self) -> Result<f32, SensorError> {
+        let raw = read_average(self.adc, &mut self.channel, self.config.samples).await?;
+        match raw {
+            raw if raw <= RAIL_MARGIN => Err(SensorError::ShortCircuit),            // Thermistor shorted to ground
+            raw if raw >= ADC_MAX - RAIL_MARGIN => Err(SensorError::OpenCircuit),   // Thermistor missing, pin pulled up by the series resistor
+            _ => Ok(self.config.series_resistor * raw as f32 / (ADC_MAX - raw) as f32),
+        }
+    }
+}
+
+impl TemperatureSensor for NtcThermistor<'_> {
+    async fn read_temperature(&mut self) -> Result<f32, SensorError> {
+        let resistance = self.resistance().await?;
+        Ok(self.config.coefficients.temperature(resistance))
+    }
+}
@@ -0,0 +1,273 @@
+use core::marker::PhantomData;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+use embassy_time::Instant;
+use embedded_graphics::prelude::*;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle, PrimitiveStyleBuilder};
+use embedded_graphics::mono_font::{ascii::{FONT_6X10, FONT_8X13}, MonoTextStyle};
+use embedded_graphics::text::Text;
+use embedded_graphics::image::{Image, ImageRaw};
+
+use crate::panel::{Layout, Panel, MAX_BLOCK_BYTES, MAX_BUFFER_SIZE};
+
+
+/// Clockwise rotation of the picture on the panel
+#[derive(Debug, Copy, Clone, PartialEq, defmt::Format)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// SH1107, SH1106 or SSD1306 OLED driver over any `display_interface` bus (SPI or I2C).
+/// The controller and geometry come from the `Panel` type.
+#[derive(Debug, Clone, Copy)]
+pub struct Oled<DI, RESET, P> {
+    interface: DI,
+    rst: Option<RESET>,     // Not every I2C module brings out the reset line
+    buffer: [u8; MAX_BUFFER_SIZE],
+    dirty: u128,            // One bit per framebuffer block (row or page) that has changed since the last `show`
+    rotation: Rotation,
+    mirror: bool,           // Flip left to right before rotating
+    shift: Point,           // Offset added to everything drawn, moved around to spread burn-in
+    panel: PhantomData<P>,
+}
+
+impl<DI, RESET, P> Oled<DI, RESET, P>
+where
+    DI: AsyncWriteOnlyDataCommand,
+    RESET: OutputPin,
+    P: Panel,
+{
+
+    pub fn new(interface: DI, rst: Option<RESET>) -> Self {
+        Self {
+            interface,
+            rst,
+            buffer: [0; MAX_BUFFER_SIZE],
+            dirty: u128::MAX,   // The panel's RAM is undefined after power up
+            rotation: Rotation::Deg0,
+            mirror: false,
+            shift: Point::zero(),
+            panel: PhantomData,
+         }
+    }
+
+    /// Rotate and mirror what is drawn from now on. The framebuffer is cleared as its contents would be in the old orientation.
+    pub fn set_orientation(&mut self, rotation: Rotation, mirror: bool) {
+        self.rotation = rotation;
+        self.mirror = mirror;
+        self.buffer.fill(0x00);
+        self.dirty = u128::MAX;
+    }
+
+    pub async fn init<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), DisplayError> {
+        self.reset(delay).await?;
+        self.off().await?;
+        for command in P::INIT {
+            self.send_commands(command).await?;
+        }
+        self.set_contrast(P::CONTRAST).await?;
+        self.on().await?;
+        delay.delay_ms(10).await;
+        Ok(())
+    }
+
+    async fn reset<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), DisplayError> {
+        let Some(rst) = self.rst.as_mut() else {
+            return Ok(());
+        };
+        rst.set_high().map_err(|_| DisplayError::RSError)?;
+        delay.delay_ms(1).await;
+        rst.set_low().map_err(|_| DisplayError::RSError)?;
+        delay.delay_ms(10).await;
+        rst.set_high().map_err(|_| DisplayError::RSError)?;
+        Ok(())
+    }
+
+    pub async fn off(&mut self) -> Result<(), DisplayError> {
+        self.send_commands(&[0xAE]).await?; // Display OFF
+        Ok(())
+    }
+
+    pub async fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+        self.send_commands(&[0x81, contrast]).await?; // Set contrast control
+        Ok(())
+    }
+
+    /// Offset everything drawn from now on. Whatever is pushed off the edge is clipped.
+    pub fn set_shift(&mut self, shift: Point) {
+        self.shift = shift;
+    }
+
+    pub async fn on(&mut self) -> Result<(), DisplayError> {
+        self.send_commands(&[0xAF]).await?; // Display ON
+        Ok(())
+    }
+
+    async fn send_commands(&mut self, commands: &[u8]) -> Result<(), DisplayError> {
+        self.interface.send_commands(DataFormat::U8(commands)).await
+    }
+
+    async fn send_data(&mut self, data: &[u8]) -> Result<(), DisplayError> {
+        self.interface.send_data(DataFormat::U8(data)).await
+    }
+
+    /// Send the blocks that have changed since the last call to the panel
+    pub async fn show(&mut self) -> Result<(), DisplayError> {
+        if self.dirty == 0 {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let blocks = self.dirty.count_ones();
+        for block in 0..P::BLOCKS {
+            if self.dirty & (1 << block) == 0 {
+                continue;
+            }
+            self.send_commands(&P::address(block as u8)).await?;
+            let index = block * P::BLOCK_BYTES;
+            let mut data = [0u8; MAX_BLOCK_BYTES];
+            data[..P::BLOCK_BYTES].copy_from_slice(&self.buffer[index..index + P::BLOCK_BYTES]);
+            self.send_data(&data[..P::BLOCK_BYTES]).await?;  // The whole block in one transfer
+            self.dirty &= !(1 << block);
+        }
+        defmt::debug!("Display refresh: {:?} blocks in {:?} us", blocks, start.elapsed().as_micros());
+        Ok(())
+    }
+
+    pub async fn clear(&mut self) -> Result<(), DisplayError> {
+        // Only blocks with something on them need sending again
+        let size = P::BLOCKS * P::BLOCK_BYTES;
+        for (block, bytes) in self.buffer[..size].chunks_exact_mut(P::BLOCK_BYTES).enumerate() {
+            if bytes.iter().any(|byte| *byte != 0) {
+                bytes.fill(0x00);
+                self.dirty |= 1 << block;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn draw_rectangle(&mut self, top_left: Point, bottom_right: Size, colour: BinaryColor, fill: bool) -> Result<(), DisplayError> {
+        let style = if fill {
+            PrimitiveStyleBuilder::new().stroke_color(colour).stroke_width(1).fill_color(colour).build()
+        } else {
+            PrimitiveStyleBuilder::new().stroke_color(colour).stroke_width(1).build()
+        };
+        let rectangle = Rectangle::new(top_left, bottom_right).into_styled(style);
+        rectangle.draw(self)?;
+        Ok(())
+    }
+
+    pub async fn draw_line(&mut self, start: Point, end: Point, colour: BinaryColor) -> Result<(), DisplayError> {
+        Line::new(start, end).into_styled(PrimitiveStyle::with_stroke(colour, 1)).draw(self)?;
+        Ok(())
+    }
+
+    pub async fn draw_pixel(&mut self, point: Point, colour: BinaryColor) -> Result<(), DisplayError> {
+        Pixel(point, colour).draw(self)?;
+        Ok(())
+    }
+
+    pub async fn draw_text(&mut self, text: &str, top_left: Point, colour: BinaryColor) -> Result<(), DisplayError> {
+        let style = MonoTextStyle::new(&FONT_8X13, colour);
+        let txt = Text::new(text, top_left, style);
+        txt.draw(self)?;
+        Ok(())
+    }
+
+    /// Text in the 6 x 10 font, for status lines that need more than 16 characters
+    pub async fn draw_small_text(&mut self, text: &str, top_left: Point, colour: BinaryColor) -> Result<(), DisplayError> {
+        let style = MonoTextStyle::new(&FONT_6X10, colour);
+        Text::new(text, top_left, style).draw(self)?;
+        Ok(())
+    }
+
+    pub async fn draw_image(
+        &mut self,
+        image_data: &[u8],
+        width: u32,
+        position: Point
+    ) -> Result<(), DisplayError> {
+        // Create image from raw data
+        let raw = ImageRaw::<BinaryColor>::new(image_data, width);
+        let image = Image::new(&raw, position);
+        // Use embedded-graphics draw trait to render
+        image.draw(self)?;
+        Ok(())
+    }
+
+}
+
+
+impl<DI, RESET, P: Panel> Oled<DI, RESET, P> {
+    /// Whether a pixel is lit in the framebuffer, in panel coordinates
+    #[cfg(test)]
+    pub(crate) fn panel_pixel(&self, x: usize, y: usize) -> bool {
+        let (index, bit) = match P::LAYOUT {
+            Layout::Rows => ((y * P::WIDTH as usize + x) / 8, x % 8),
+            Layout::Pages => ((y / 8) * P::WIDTH as usize + x, y % 8),
+        };
+        self.buffer[index] & (1 << bit) != 0
+    }
+
+    /// Map a point in the rotated and mirrored picture to the panel, None if it is off the panel
+    fn to_panel(&self, point: Point) -> Option<(usize, usize)> {
+        let size = self.bounding_box().size;
+        let (width, height) = (size.width as i32, size.height as i32);
+        let (x, y) = (point.x + self.shift.x, point.y + self.shift.y);
+        if x < 0 || x >= width || y < 0 || y >= height {
+            return None;
+        }
+        let x = if self.mirror { width - 1 - x } else { x };
+        let (x, y) = match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (P::WIDTH as i32 - 1 - y, x),
+            Rotation::Deg180 => (P::WIDTH as i32 - 1 - x, P::HEIGHT as i32 - 1 - y),
+            Rotation::Deg270 => (y, P::HEIGHT as i32 - 1 - x),
+        };
+        Some((x as usize, y as usize))
+    }
+}
+
+/// Drawing only updates the framebuffer and marks the blocks it changed, `show` sends them to the panel
+impl<DI, RESET, P: Panel> DrawTarget for Oled<DI, RESET, P> {
+    type Color = BinaryColor;
+    type Error = DisplayError;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(coord, colour) in pixels {
+            if let Some((x, y)) = self.to_panel(coord) {
+                let (index, bit, block) = match P::LAYOUT {
+                    Layout::Rows => ((y * P::WIDTH as usize + x) / 8, x % 8, y),
+                    Layout::Pages => ((y / 8) * P::WIDTH as usize + x, y % 8, y / 8),
+                };
+                let old = self.buffer[index];
+                if colour == BinaryColor::On {
+                    self.buffer[index] |= 1 << bit;
+                } else {
+                    self.buffer[index] &= !(1 << bit);
+                }
+                if self.buffer[index] != old {
+                    self.dirty |= 1 << block;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The size of the picture, which is the panel turned on its side for 90 and 270 degrees
+impl<DI, RST, P: Panel> Dimensions for Oled<DI, RST, P> {
+    fn bounding_box(&self) -> Rectangle {
+        match self.rotation {
+            Rotation::Deg0 | Rotation::Deg180 => Rectangle::new(Point::zero(), Size::new(P::WIDTH, P::HEIGHT)),
+            Rotation::Deg90 | Rotation::Deg270 => Rectangle::new(Point::zero(), Size::new(P::HEIGHT, P::WIDTH)),
+        }
+    }
+}
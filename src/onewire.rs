@@ -0,0 +1,25 @@
+use crate::sensor::SensorError;
+
+/// Result of a search triplet: the two bits read from the bus and the direction that was written
+#[derive(Copy, Clone)]
+pub struct Triplet {
+    pub id_bit: bool,
+    pub complement_bit: bool,
+    pub direction: bool,
+}
+
+/// The 1-Wire bus operations the `Ds18b20` driver needs
+#[allow(async_fn_in_trait)]
+pub trait OneWire {
+    /// Reset the bus. Returns true if a device answered with a presence pulse.
+    async fn reset(&mut self) -> Result<bool, SensorError>;
+
+    /// Write bytes LSB first. Returns once the last time slot has finished and the bus is released, so a strong
+    /// pull-up can be switched on straight after.
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SensorError>;
+
+    async fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SensorError>;
+
+    /// One step of a ROM search: read a bit and its complement, then write `direction` unless the bits decide it
+    async fn triplet(&mut self, direction: bool) -> Result<Triplet, SensorError>;
+}
@@ -0,0 +1,162 @@
+/// How framebuffer bytes map onto the controller's RAM
+#[derive(Copy, Clone, PartialEq)]
+pub enum Layout {
+    Rows,   // Each byte is 8 pixels across, a framebuffer row is written as one column of pages (SH1107 vertical addressing)
+    Pages,  // Each byte is 8 pixels down, a page of 8 rows is written left to right (SSD1306 / SH1106 page addressing)
+}
+
+/// The controller and geometry of an OLED module
+pub trait Panel {
+    const WIDTH: u32;
+    const HEIGHT: u32;
+    const LAYOUT: Layout;
+    /// Contrast set by `init` and restored when the display wakes
+    const CONTRAST: u8;
+    /// Sent once after reset with the panel off
+    const INIT: &'static [&'static [u8]];
+
+    /// Blocks the framebuffer is flushed in (rows or pages)
+    const BLOCKS: usize = match Self::LAYOUT {
+        Layout::Rows => Self::HEIGHT as usize,
+        Layout::Pages => Self::HEIGHT as usize / 8,
+    };
+    /// Bytes in each block
+    const BLOCK_BYTES: usize = match Self::LAYOUT {
+        Layout::Rows => Self::WIDTH as usize / 8,
+        Layout::Pages => Self::WIDTH as usize,
+    };
+
+    /// Commands that point the controller's RAM at the start of a block
+    fn address(block: u8) -> [u8; 3];
+}
+
+/// Largest framebuffer of the supported panels (128 x 128)
+pub const MAX_BUFFER_SIZE: usize = 128 * 128 / 8;
+/// Largest block of the supported panels
+pub const MAX_BLOCK_BYTES: usize = 128;
+
+/// SH1107 driving a 128 x 64 module (Waveshare Pico-OLED-1.3), mounted across a 64 column panel
+pub struct Sh1107x64;
+
+/// SH1107 driving a 128 x 128 module
+pub struct Sh1107x128;
+
+/// SSD1306 128 x 64 module
+pub struct Ssd1306;
+
+/// SH1106 128 x 64 module. The controller has 132 columns and the glass starts at column 2.
+pub struct Sh1106;
+
+/// Page, lower and higher column address for an SH1107 row in vertical addressing mode, bottom row first
+fn sh1107_address(row: u8, height: u32) -> [u8; 3] {
+    let column = (height - 1) as u8 - row;
+    [0xB0, column & 0x0F, 0x10 + (column >> 4)]
+}
+
+impl Panel for Sh1107x64 {
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 64;
+    const LAYOUT: Layout = Layout::Rows;
+    const CONTRAST: u8 = 0x6F;
+    const INIT: &'static [&'static [u8]] = &[
+        &[0x00],        // Set lower column address
+        &[0x10],        // Set higher column address
+        &[0xB0],        // Set page address
+        &[0xDC, 0x00],  // Set display start line
+        &[0x21],        // Set memory addressing mode (0x20 = Horizontal / 0x21 = Vertical)
+        &[0xA0],        // Set segment remap (0xA0 / 0xA1)
+        &[0xC0],        // Set common output scan direction (0xC0 / 0xC8)
+        &[0xA4],        // Set entire display on (0xA4 = false / 0xA5 = true)
+        &[0xA6],        // Set normal or reverse display on (0xA6 = Normal / 0xA7 = Reverse)
+        &[0xA8, 0x3F],  // Set multiplex ratio (Display height - 1)
+        &[0xD3, 0x60],  // Set display offset (the 64 columns of glass sit at the end of the 128 row RAM)
+        &[0xD5, 0x50],  // Clock divide ratio / oscillator frequency mode
+        &[0xD9, 0x22],  // Set discharge / precharge period
+        &[0xDB, 0x35],  // Set VCOM deselect level
+        &[0xAD, 0x81],  // Set DC-DC control mode (0x81 = On / 0x80 = Off)
+    ];
+
+    fn address(block: u8) -> [u8; 3] {
+        sh1107_address(block, Self::HEIGHT)
+    }
+}
+
+impl Panel for Sh1107x128 {
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 128;
+    const LAYOUT: Layout = Layout::Rows;
+    const CONTRAST: u8 = 0x6F;
+    const INIT: &'static [&'static [u8]] = &[
+        &[0x00],        // Set lower column address
+        &[0x10],        // Set higher column address
+        &[0xB0],        // Set page address
+        &[0xDC, 0x00],  // Set display start line
+        &[0x21],        // Set memory addressing mode (0x20 = Horizontal / 0x21 = Vertical)
+        &[0xA0],        // Set segment remap (0xA0 / 0xA1)
+        &[0xC0],        // Set common output scan direction (0xC0 / 0xC8)
+        &[0xA4],        // Set entire display on (0xA4 = false / 0xA5 = true)
+        &[0xA6],        // Set normal or reverse display on (0xA6 = Normal / 0xA7 = Reverse)
+        &[0xA8, 0x7F],  // Set multiplex ratio (Display height - 1)
+        &[0xD3, 0x00],  // Set display offset
+        &[0xD5, 0x51],  // Clock divide ratio / oscillator frequency mode
+        &[0xD9, 0x22],  // Set discharge / precharge period
+        &[0xDB, 0x35],  // Set VCOM deselect level
+        &[0xAD, 0x81],  // Set DC-DC control mode (0x81 = On / 0x80 = Off)
+    ];
+
+    fn address(block: u8) -> [u8; 3] {
+        sh1107_address(block, Self::HEIGHT)
+    }
+}
+
+impl Panel for Ssd1306 {
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 64;
+    const LAYOUT: Layout = Layout::Pages;
+    const CONTRAST: u8 = 0xCF;
+    const INIT: &'static [&'static [u8]] = &[
+        &[0xD5, 0x80],  // Clock divide ratio / oscillator frequency
+        &[0xA8, 0x3F],  // Set multiplex ratio (Display height - 1)
+        &[0xD3, 0x00],  // Set display offset
+        &[0x40],        // Set display start line
+        &[0x8D, 0x14],  // Charge pump on
+        &[0x20, 0x02],  // Set memory addressing mode (0x02 = Page)
+        &[0xA1],        // Set segment remap (column 127 is SEG0)
+        &[0xC8],        // Set common output scan direction (remapped)
+        &[0xDA, 0x12],  // COM pins hardware configuration (alternative, no left / right remap)
+        &[0xD9, 0xF1],  // Set precharge period
+        &[0xDB, 0x40],  // Set VCOMH deselect level
+        &[0xA4],        // Set entire display on (0xA4 = false / 0xA5 = true)
+        &[0xA6],        // Set normal or reverse display on (0xA6 = Normal / 0xA7 = Reverse)
+    ];
+
+    fn address(block: u8) -> [u8; 3] {
+        [0xB0 + block, 0x00, 0x10]  // Set page, lower and higher column address
+    }
+}
+
+impl Panel for Sh1106 {
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 64;
+    const LAYOUT: Layout = Layout::Pages;
+    const CONTRAST: u8 = 0x80;
+    const INIT: &'static [&'static [u8]] = &[
+        &[0xD5, 0x80],  // Clock divide ratio / oscillator frequency
+        &[0xA8, 0x3F],  // Set multiplex ratio (Display height - 1)
+        &[0xD3, 0x00],  // Set display offset
+        &[0x40],        // Set display start line
+        &[0xAD, 0x8B],  // Set DC-DC control mode (0x8B = On / 0x8A = Off)
+        &[0x33],        // Set pump voltage (9V)
+        &[0xA1],        // Set segment remap (column 131 is SEG0)
+        &[0xC8],        // Set common output scan direction (remapped)
+        &[0xDA, 0x12],  // COM pins hardware configuration
+        &[0xD9, 0x1F],  // Set discharge / precharge period
+        &[0xDB, 0x40],  // Set VCOM deselect level
+        &[0xA4],        // Set entire display on (0xA4 = false / 0xA5 = true)
+        &[0xA6],        // Set normal or reverse display on (0xA6 = Normal / 0xA7 = Reverse)
+    ];
+
+    fn address(block: u8) -> [u8; 3] {
+        [0xB0 + block, 0x02, 0x10]  // Set page, lower and higher column address (column 2)
+    }
+}
@@ -0,0 +1,130 @@
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::gpio::Level;
+use embassy_rp::pio::{self, Direction};
+
+use crate::onewire::{OneWire, Triplet};
+use crate::sensor::SensorError;
+
+/// PIO program for a 1-Wire bus, shared by every bus on the same PIO block.
+/// The pin is only ever driven low or released, so the bus needs its usual 4.7k pull-up.
+/// It runs at 1 MHz so each delay cycle is 1 us. The first word of each command picks a reset (1) or a number of time
+/// slots ((slots - 1) << 1), which are followed by a word of bits to write, LSB first. A 1 bit is also a read slot.
+/// The presence bit or the bits read back are pushed once the last slot has finished and the bus is released.
+pub struct PioOneWireProgram<'a, PIO: pio::Instance> {
+    program: pio::LoadedProgram<'a, PIO>,
+}
+
+impl<'a, PIO: pio::Instance> PioOneWireProgram<'a, PIO> {
+    pub fn new(common: &mut pio::Common<'a, PIO>) -> Self {
+        let program = pio::program::pio_asm!(
+            r#"
+                .wrap_target
+                start:
+                    pull block
+                    out x, 1
+                    jmp !x, slots
+                    set pindirs, 1              ; Reset: hold the bus low for 513 us
+                    set x, 14           [31]
+                reset_low:
+                    jmp x--, reset_low  [31]
+                    set pindirs, 0      [31]    ; Release and sample for a presence pulse 70 us later
+                    nop                 [31]
+                    nop                 [5]
+                    in pins, 1          [31]
+                    set x, 11
+                reset_high:
+                    jmp x--, reset_high [31]    ; Let the presence pulse finish (480 us in all)
+                    push block
+                    jmp start
+                slots:
+                    out y, 31
+                    pull block
+                slot:
+                    out x, 1
+                    set pindirs, 1      [1]     ; Every slot starts by pulling the bus low
+                    jmp !x, zero
+                    set pindirs, 0      [9]     ; A 1 (or a read) releases it after 3 us
+                zero:
+                    in pins, 1          [31]    ; Sample 13 us into the slot
+                    nop                 [31]
+                    set pindirs, 0      [3]     ; A 0 is released after 67 us
+                    jmp y--, slot
+                    push block
+                .wrap
+            "#,
+        );
+        Self { program: common.load_program(&program.program) }
+    }
+}
+
+/// A 1-Wire bus driven by a PIO state machine
+pub struct PioOneWire<'d, PIO: pio::Instance, const SM: usize> {
+    sm: pio::StateMachine<'d, PIO, SM>,
+}
+
+impl<'d, PIO: pio::Instance, const SM: usize> PioOneWire<'d, PIO, SM> {
+    pub fn new(
+        common: &mut pio::Common<'d, PIO>,
+        mut sm: pio::StateMachine<'d, PIO, SM>,
+        pin: impl pio::PioPin,
+        program: &PioOneWireProgram<'d, PIO>,
+    ) -> Self {
+        let pin = common.make_pio_pin(pin);
+        let mut config = pio::Config::default();
+        config.use_program(&program.program, &[]);
+        config.set_in_pins(&[&pin]);
+        config.set_set_pins(&[&pin]);
+        config.shift_in = pio::ShiftConfig { auto_fill: false, threshold: 32, direction: pio::ShiftDirection::Right };
+        config.shift_out = pio::ShiftConfig { auto_fill: false, threshold: 32, direction: pio::ShiftDirection::Right };
+        config.clock_divider = ((clk_sys_freq() / 1_000_000) as u16).into();
+        sm.set_config(&config);
+        sm.set_pins(Level::Low, &[&pin]);           // Driving the pin only ever pulls the bus low
+        sm.set_pin_dirs(Direction::In, &[&pin]);    // Start with the bus released
+        sm.set_enable(true);
+        Self { sm }
+    }
+
+    /// Run up to 32 time slots, writing `bits` LSB first. Returns the bits read back, LSB first.
+    /// The state machine only pushes them after the last slot, so the TX FIFO has drained and it is back waiting on
+    /// its next command by the time this returns.
+    async fn slots(&mut self, bits: u32, count: u8) -> u32 {
+        self.sm.tx().wait_push((count as u32 - 1) << 1).await;
+        self.sm.tx().wait_push(bits).await;
+        self.sm.rx().wait_pull().await >> (32 - count as u32)
+    }
+}
+
+/// Every operation waits for the state machine to report back, so when one returns it has finished on the wire
+impl<PIO: pio::Instance, const SM: usize> OneWire for PioOneWire<'_, PIO, SM> {
+    async fn reset(&mut self) -> Result<bool, SensorError> {
+        self.sm.tx().wait_push(1).await;
+        let sample = self.sm.rx().wait_pull().await;
+        Ok(sample & 0x8000_0000 == 0)  // A device holds the bus low to show it is present
+    }
+
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SensorError> {
+        for byte in bytes {
+            self.slots(*byte as u32, 8).await;
+        }
+        Ok(())
+    }
+
+    async fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), SensorError> {
+        for byte in buffer.iter_mut() {
+            *byte = self.slots(0xFF, 8).await as u8;
+        }
+        Ok(())
+    }
+
+    async fn triplet(&mut self, direction: bool) -> Result<Triplet, SensorError> {
+        let bits = self.slots(0b11, 2).await;
+        let id_bit = bits & 0x01 != 0;
+        let complement_bit = bits & 0x02 != 0;
+        let direction = match (id_bit, complement_bit) {
+            (false, false) => direction,    // Devices differ at this bit, take the direction asked for
+            (id_bit, _) => id_bit,          // Every device agrees (or nothing answered)
+        };
+        self.slots(direction as u32, 1).await;
+        Ok(Triplet { id_bit, complement_bit, direction })
+    }
+}
@@ -0,0 +1,109 @@
+use embassy_rp::adc::Channel;
+
+use crate::analog::{read_average, SharedAdc, ADC_MAX};
+use crate::sensor::SensorError;
+
+const ADC_REFERENCE: f32 = 3.3;     // ADC reference voltage
+const PSI_PER_BAR: f32 = 14.5038;
+
+/// Unit pressures are configured and displayed in. Pressures are always held in bar internally.
+#[derive(Copy, Clone, PartialEq)]
+pub enum PressureUnit {
+    Psi,
+    Bar,
+}
+
+impl PressureUnit {
+    pub fn to_bar(&self, value: f32) -> f32 {
+        match self {
+            PressureUnit::Psi => value / PSI_PER_BAR,
+            PressureUnit::Bar => value,
+        }
+    }
+
+    pub fn from_bar(&self, bar: f32) -> f32 {
+        match self {
+            PressureUnit::Psi => bar * PSI_PER_BAR,
+            PressureUnit::Bar => bar,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PressureUnit::Psi => "psi",
+            PressureUnit::Bar => "bar",
+        }
+    }
+}
+
+/// Settings for a ratiometric analog pressure transducer (e.g. 0.5 - 4.5V for 0 - full scale)
+#[derive(Copy, Clone)]
+pub struct TransducerConfig {
+    pub zero_voltage: f32,      // Output at 0 bar (gauge)
+    pub full_voltage: f32,      // Output at full scale
+    pub full_scale: f32,        // Full scale pressure (bar)
+    pub divider_ratio: f32,     // Voltage divider between the transducer and the ADC pin (ADC voltage / transducer voltage)
+    pub samples: u8,            // Number of ADC samples averaged for each reading
+}
+
+/// Analog pressure transducer read through the RP2040 ADC
+pub struct PressureTransducer<'d> {
+    adc: &'d SharedAdc<'d>,
+    channel: Channel<'d>,
+    config: TransducerConfig,
+}
+
+impl<'d> PressureTransducer<'d> {
+    pub fn new(adc: &'d SharedAdc<'d>, channel: Channel<'d>, config: TransducerConfig) -> Self {
+        Self { adc, channel, config }
+    }
+
+    /// Gauge pressure in bar
+    pub async fn pressure(&mut self) -> Result<f32, SensorError> {
+        let raw = read_average(self.adc, &mut self.channel, self.config.samples).await?;
+        let voltage = raw as f32 * ADC_REFERENCE / ADC_MAX as f32 / self.config.divider_ratio;
+        let span = self.config.full_voltage - self.config.zero_voltage;
+        // A healthy transducer never leaves its output range by more than a few percent
+        if voltage < self.config.zero_voltage - span * 0.05 {
+            Err(SensorError::OpenCircuit)
+        } else if voltage > self.config.full_voltage + span * 0.05 {
+            Err(SensorError::OverRange)     // Over pressure, or the output is shorted to the supply
+        } else {
+            Ok(((voltage - self.config.zero_voltage) / span * self.config.full_scale).max(0.0))
+        }
+    }
+}
+
+/// Decides when the spunding solenoid should vent
+pub struct SpundingValve {
+    target: Option<f32>,    // Pressure to hold (bar), None to only vent at the hard limit
+    limit: f32,             // Hard over-pressure limit (bar), always vented regardless of the target
+    hysteresis: f32,        // The valve closes this far below the target (bar)
+    open: bool,
+}
+
+impl SpundingValve {
+    pub fn new(target: Option<f32>, limit: f32, hysteresis: f32) -> Self {
+        Self { target, limit, hysteresis, open: false }
+    }
+
+    pub fn set_target(&mut self, target: Option<f32>) {
+        self.target = target;
+    }
+
+    /// Check if the pressure is at or over the hard limit
+    pub fn over_limit(&self, pressure: f32) -> bool {
+        pressure >= self.limit
+    }
+
+    /// Work out whether the valve should be open for the given pressure (bar)
+    pub fn update(&mut self, pressure: f32) -> bool {
+        let target = self.target.unwrap_or(self.limit).min(self.limit);
+        if pressure > target {
+            self.open = true;
+        } else if pressure < target - self.hysteresis {
+            self.open = false;
+        }
+        self.open
+    }
+}
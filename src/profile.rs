@@ -0,0 +1,60 @@
+use crate::hx711::FermentProgress;
+
+/// What moves a profile on from a step
+#[derive(Copy, Clone)]
+pub enum StepEnd {
+    Hours(u32),     // After the step has run for this many hours
+    Co2Loss(f32),   // Once the fermenter has lost this many grams of CO2 since the scale's start weight
+    GravityDrop(f32),   // Once the estimated gravity has fallen by this much since the start weight (e.g. 0.030)
+    Manual,         // Never, the profile stays on this step
+}
+
+/// One stage of a fermentation profile
+#[derive(Copy, Clone)]
+pub struct ProfileStep {
+    pub target_temp: f32,           // Degrees C
+    pub pressure: Option<f32>,      // Spunding target (in the configured pressure unit), None to leave the valve closed below the hard limit
+    pub end: StepEnd,
+}
+
+/// Steps through a fixed fermentation profile
+pub struct ProfileRunner<'a> {
+    steps: &'a [ProfileStep],
+    step: usize,
+    step_started: u64,      // Time the current step started (seconds)
+}
+
+impl<'a> ProfileRunner<'a> {
+    pub fn new(steps: &'a [ProfileStep], now: u64) -> Self {
+        Self { steps, step: 0, step_started: now }
+    }
+
+    /// The step the profile is on, None if the profile is empty
+    pub fn current(&self) -> Option<&ProfileStep> {
+        self.steps.get(self.step)
+    }
+
+    /// Index of the step the profile is on
+    pub fn step_index(&self) -> usize {
+        self.step
+    }
+
+    /// Move on to the next step if the current one has ended. Returns the new step if it changed.
+    /// Weight-based steps never end while `progress` is None (no scale, or a failed reading).
+    pub fn update(&mut self, now: u64, progress: Option<FermentProgress>) -> Option<ProfileStep> {
+        let step = self.current()?;
+        let ended = match step.end {
+            StepEnd::Hours(hours) => now >= self.step_started + hours as u64 * 3600,
+            StepEnd::Co2Loss(grams) => progress.is_some_and(|progress| progress.co2_loss >= grams),
+            StepEnd::GravityDrop(drop) => progress.is_some_and(|progress| progress.gravity_drop >= drop),
+            StepEnd::Manual => false,
+        };
+        if ended && self.step + 1 < self.steps.len() {
+            self.step += 1;
+            self.step_started = now;
+            self.current().copied()
+        } else {
+            None
+        }
+    }
+}
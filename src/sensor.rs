@@ -1,9 +1,19 @@
 
-use embassy_rp::pio;
-use embassy_rp::pio_programs::onewire::PioOneWire; //, PioOneWireProgram}};
+use embedded_hal::digital::OutputPin;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+
+use crate::onewire::OneWire;
+use crate::temperature::TemperatureSensor;
+
+pub const FAMILY_CODE: u8 = 0x28;           // First byte of every DS18B20 ROM code
+const POWER_ON_RESET_VALUE: i16 = 0x0550;   // Raw temperature register value after power-up (85 degrees C)
+const POWER_ON_RESERVED: u8 = 0x0C;         // Reserved scratchpad byte 6 after power-up, before the first conversion
+const CONVERSION_POLL_MS: u64 = 10;         // Interval between read slots while polling for a finished conversion
+const EEPROM_WRITE_MS: u64 = 10;            // Time the sensor needs to copy the scratchpad to EEPROM
 
 /// Resolution settings for temperature readings
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum Resolution {
     Bits9  = 0x1F, // 0.5°C resolution, 93.75ms conversion time
     Bits10 = 0x3F, // 0.25°C resolution, 187.5ms conversion time
@@ -11,68 +21,376 @@ pub enum Resolution {
     Bits12 = 0x7F, // 0.0625°C resolution, 750ms conversion time
 }
 
-/// DS18B20 temperature sensor driver
-pub struct Ds18b20<'d, PIO: pio::Instance, const SM: usize> {
-    wire: PioOneWire<'d, PIO, SM>,
+impl Resolution {
+    /// Decode the resolution from the configuration register
+    pub fn from_config(config: u8) -> Self {
+        match config & 0x60 {
+            0x00 => Resolution::Bits9,
+            0x20 => Resolution::Bits10,
+            0x40 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    /// Maximum time the sensor needs to finish a conversion at this resolution
+    pub fn conversion_time(&self) -> Duration {
+        match self {
+            Resolution::Bits9 => Duration::from_micros(93_750),
+            Resolution::Bits10 => Duration::from_micros(187_500),
+            Resolution::Bits11 => Duration::from_millis(375),
+            Resolution::Bits12 => Duration::from_millis(750),
+        }
+    }
+}
+
+/// Settings held in the scratchpad and saved to the sensor's EEPROM
+#[derive(Copy, Clone, PartialEq)]
+pub struct SensorConfig {
+    pub alarm_high: i8,         // TH register, an alarm is flagged above this temperature (degrees C)
+    pub alarm_low: i8,          // TL register, an alarm is flagged below this temperature (degrees C)
+    pub resolution: Resolution,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            alarm_high: 125,    // Limits of the sensor's range so no alarm is flagged
+            alarm_low: -55,
+            resolution: Resolution::Bits12,
+        }
+    }
+}
+
+/// How a sensor is powered, as reported by Read Power Supply
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum PowerSupply {
+    External,
+    Parasite,
+}
+
+impl PowerSupply {
+    /// Short description of the power mode for the display
+    pub fn description(&self) -> &'static str {
+        match self {
+            PowerSupply::External => "EXTERNAL",
+            PowerSupply::Parasite => "PARASITE",
+        }
+    }
+}
+
+/// Faults that can occur when reading a temperature sensor
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum SensorError {
+    NoPresence,     // Nothing answered on the bus (every bit read back high)
+    CrcMismatch,    // The data failed its CRC check
+    PowerOnReset,   // The sensor returned its power-on value, so no conversion has completed
+    BusShorted,     // The data line is held low (every bit read back low)
+    RomNotFound,    // No device answered to the addressed ROM code
+    ConfigMismatch, // The configuration read back did not match what was written
+    OpenCircuit,    // The probe or one of its wires is disconnected (analog and RTD probes)
+    ShortCircuit,   // The probe or its wiring is shorted (analog and RTD probes)
+    ShortToGround,  // The thermocouple is shorted to GND
+    ShortToVcc,     // The thermocouple is shorted to VCC
+    BusError,       // The SPI or ADC transfer itself failed
+    OverRange,      // The reading is above the sensor's range (analog transducers)
+}
+
+impl SensorError {
+    /// Short description of the fault, sized to fit one line of the display
+    pub fn description(&self) -> &'static str {
+        match self {
+            SensorError::NoPresence => "SENSOR NOT FOUND",
+            SensorError::CrcMismatch => "SENSOR CRC ERROR",
+            SensorError::PowerOnReset => "SENSOR NOT READY",
+            SensorError::BusShorted => "SENSOR BUS SHORT",
+            SensorError::RomNotFound => " ROM NOT FOUND  ",
+            SensorError::ConfigMismatch => "SENSOR CFG ERROR",
+            SensorError::OpenCircuit => "SENSOR OPEN CCT ",
+            SensorError::ShortCircuit => " SENSOR SHORTED ",
+            SensorError::ShortToGround => "SENSOR SHORT GND",
+            SensorError::ShortToVcc => "SENSOR SHORT VCC",
+            SensorError::BusError => "SENSOR BUS ERROR",
+            SensorError::OverRange => "SENSOR OVERRANGE",
+        }
+    }
+}
+
+/// Running count of each kind of fault seen on a sensor
+#[derive(Copy, Clone, defmt::Format)]
+pub struct ErrorCounters {
+    pub no_presence: u32,
+    pub crc_mismatch: u32,
+    pub power_on_reset: u32,
+    pub bus_shorted: u32,
+    pub rom_not_found: u32,
+    pub config_mismatch: u32,
+    pub open_circuit: u32,
+    pub short_circuit: u32,
+    pub short_to_ground: u32,
+    pub short_to_vcc: u32,
+    pub bus_error: u32,
+    pub over_range: u32,
+}
+
+impl ErrorCounters {
+    pub const fn new() -> Self {
+        Self {
+            no_presence: 0,
+            crc_mismatch: 0,
+            power_on_reset: 0,
+            bus_shorted: 0,
+            rom_not_found: 0,
+            config_mismatch: 0,
+            open_circuit: 0,
+            short_circuit: 0,
+            short_to_ground: 0,
+            short_to_vcc: 0,
+            bus_error: 0,
+            over_range: 0,
+        }
+    }
+
+    pub fn record(&mut self, error: SensorError) {
+        let counter = match error {
+            SensorError::NoPresence => &mut self.no_presence,
+            SensorError::CrcMismatch => &mut self.crc_mismatch,
+            SensorError::PowerOnReset => &mut self.power_on_reset,
+            SensorError::BusShorted => &mut self.bus_shorted,
+            SensorError::RomNotFound => &mut self.rom_not_found,
+            SensorError::ConfigMismatch => &mut self.config_mismatch,
+            SensorError::OpenCircuit => &mut self.open_circuit,
+            SensorError::ShortCircuit => &mut self.short_circuit,
+            SensorError::ShortToGround => &mut self.short_to_ground,
+            SensorError::ShortToVcc => &mut self.short_to_vcc,
+            SensorError::BusError => &mut self.bus_error,
+            SensorError::OverRange => &mut self.over_range,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    pub fn total(&self) -> u32 {
+        self.no_presence + self.crc_mismatch + self.power_on_reset + self.bus_shorted + self.rom_not_found + self.config_mismatch
+            + self.open_circuit + self.short_circuit + self.short_to_ground + self.short_to_vcc + self.bus_error + self.over_range
+    }
+}
+
+impl Default for ErrorCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many times a failed read is retried and how long to wait between attempts
+#[derive(Copy, Clone)]
+pub struct RetryPolicy {
+    pub attempts: u8,       // Total number of attempts, including the first
+    pub backoff_ms: u64,    // Delay before the first retry, doubled for each one after that
 }
 
-impl<'d, PIO: pio::Instance, const SM: usize> Ds18b20<'d, PIO, SM> {
-    pub fn new(wire: PioOneWire<'d, PIO, SM>) -> Self {
-        Self { wire }
+impl RetryPolicy {
+    /// Delay before the given retry (0 = first retry)
+    pub fn backoff(&self, retry: u8) -> Duration {
+        Duration::from_millis(self.backoff_ms << retry.min(16))
     }
+}
 
-    pub async fn search_for_roms(&mut self) -> Result<[Option<[u8; 8]>; 8], ()> {
+/// Devices that have appeared on or disappeared from a bus since the last scan
+pub struct RosterChanges {
+    pub added: Vec<[u8; 8], 8>,
+    pub removed: Vec<[u8; 8], 8>,
+}
+
+/// Keeps track of the ROM codes on a bus so sensors that are plugged in or unplugged can be spotted
+pub struct SensorRoster {
+    present: Vec<[u8; 8], 8>,   // Devices found by the last scan
+    missing: Vec<[u8; 8], 8>,   // Devices that were seen before but not found by the last scan
+}
+
+impl SensorRoster {
+    pub const fn new() -> Self {
+        Self { present: Vec::new(), missing: Vec::new() }
+    }
+
+    pub fn present(&self) -> &[[u8; 8]] {
+        &self.present
+    }
+
+    pub fn missing(&self) -> &[[u8; 8]] {
+        &self.missing
+    }
+
+    /// Compare the result of a ROM search against the last one
+    pub fn update(&mut self, found: &[Option<[u8; 8]>; 8]) -> RosterChanges {
+        let mut changes = RosterChanges { added: Vec::new(), removed: Vec::new() };
+        // Devices that were there last time but weren't found now
+        for rom in self.present.iter() {
+            if !found.contains(&Some(*rom)) {
+                let _ = changes.removed.push(*rom);
+                if !self.missing.contains(rom) {
+                    let _ = self.missing.push(*rom);
+                }
+            }
+        }
+        // Devices found now that weren't there last time
+        for rom in found.iter().flatten() {
+            if !self.present.contains(rom) {
+                let _ = changes.added.push(*rom);
+            }
+        }
+        self.present = found.iter().flatten().copied().collect();
+        self.missing.retain(|rom| !self.present.contains(rom));
+        changes
+    }
+
+    /// The DS18B20 to read as the control probe: the current one while it is still present, otherwise the first one found
+    pub fn choose_probe(&self, current: Option<[u8; 8]>) -> Option<[u8; 8]> {
+        match current {
+            Some(rom) if self.present.contains(&rom) => Some(rom),
+            _ => self.present.iter().find(|rom| rom[0] == FAMILY_CODE).copied(),
+        }
+    }
+}
+
+impl Default for SensorRoster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DS18B20 temperature sensor driver, on the PIO or a DS2482 bridge
+pub struct Ds18b20<W: OneWire, P: OutputPin> {
+    wire: W,
+    config: SensorConfig,       // Configuration last written to the sensors
+    power_supply: PowerSupply,  // Completion can only be polled with read slots when externally powered
+    strong_pullup: Option<P>,   // Drives a transistor that holds the bus high while parasite powered devices convert
+    offsets: Vec<([u8; 8], f32), 8>,    // Calibration offsets added to the readings of known devices (degrees C)
+    probe: Option<[u8; 8]>,     // Device read by `read_temperature`, None to read the only device on the bus with Skip ROM
+}
+
+impl<W: OneWire, P: OutputPin> Ds18b20<W, P> {
+    pub fn new(wire: W) -> Self {
+        Self {
+            wire,
+            config: SensorConfig::default(),
+            power_supply: PowerSupply::Parasite,    // Assume the worst until `detect_power_supply` is called
+            strong_pullup: None,
+            offsets: Vec::new(),
+            probe: None,
+        }
+    }
+
+    /// The bus the sensors are on, for other devices that share it such as 1-Wire switches
+    pub fn wire(&mut self) -> &mut W {
+        &mut self.wire
+    }
+
+    /// Set the calibration offset added to the readings of a specific device (degrees C)
+    pub fn set_offset(&mut self, rom: &[u8; 8], offset: f32) {
+        match self.offsets.iter_mut().find(|(known, _)| known == rom) {
+            Some((_, known_offset)) => *known_offset = offset,
+            None => {
+                let _ = self.offsets.push((*rom, offset));
+            }
+        }
+    }
+
+    /// The calibration offset for a device, 0 if it hasn't been calibrated
+    pub fn offset(&self, rom: &[u8; 8]) -> f32 {
+        self.offsets.iter().find(|(known, _)| known == rom).map_or(0.0, |(_, offset)| *offset)
+    }
+
+    /// Choose the device `read_temperature` reads, so its calibration is applied and other devices on the bus don't
+    /// collide with it. None reads the only device with Skip ROM.
+    pub fn set_probe(&mut self, rom: Option<[u8; 8]>) {
+        self.probe = rom;
+    }
+
+    /// The device read by `read_temperature`
+    pub fn probe(&self) -> Option<[u8; 8]> {
+        self.probe
+    }
+
+    /// Use a GPIO pin (active high) to switch a strong pull-up onto the bus during conversions and EEPROM writes
+    pub fn set_strong_pullup(&mut self, pin: P) {
+        self.strong_pullup = Some(pin);
+    }
+
+    /// Check if a strong pull-up pin has been set
+    pub fn has_strong_pullup(&self) -> bool {
+        self.strong_pullup.is_some()
+    }
+
+    /// Ask the devices on the bus how they are powered and use the result for later conversions.
+    /// If nothing answers the power mode is left as it was.
+    pub async fn detect_power_supply(&mut self) -> Result<PowerSupply, SensorError> {
+        self.power_supply = self.read_power_supply().await?;
+        Ok(self.power_supply)
+    }
+
+    /// The power mode found by `detect_power_supply`
+    pub fn power_supply(&self) -> PowerSupply {
+        self.power_supply
+    }
+
+    /// Find the ROM codes of every device on the bus
+    pub async fn search_for_roms(&mut self) -> Result<[Option<[u8; 8]>; 8], SensorError> {
+        self.search(0xF0).await
+    }
+
+    /// Find the ROM codes of the devices whose last reading was outside their TH/TL alarm limits.
+    /// An empty result is only returned when a device answered the reset, so a missing or faulty bus is an error.
+    pub async fn alarm_search(&mut self) -> Result<[Option<[u8; 8]>; 8], SensorError> {
+        self.search(0xEC).await
+    }
+
+    /// Check whether any DS18B20 is outside its TH/TL alarm limits.
+    /// Other devices that answer the Alarm Search, like a DS2408 on a shared bus, are ignored.
+    pub async fn temperature_alarm(&mut self) -> Result<bool, SensorError> {
+        let devices = self.alarm_search().await?;
+        Ok(devices.iter().flatten().any(|rom| rom[0] == FAMILY_CODE))
+    }
+
+    /// Run a ROM search with the given search command (Search ROM or Alarm Search).
+    /// Follows Maxim AN187, where bit numbers start at 1 so a last discrepancy of 0 means there are no branches left.
+    async fn search(&mut self, command: u8) -> Result<[Option<[u8; 8]>; 8], SensorError> {
         let mut devices = [None; 8];  // Max 8 devices supported
         let mut device_count = 0;
-        let mut last_discrepancy = 0;
+        let mut last_discrepancy = 0;   // Bit number of the last 0 branch taken where devices differ
         let mut rom_bits = [false; 64];
-    
-        'search: loop {
+
+        loop {
             let mut current_rom = [0u8; 8];
-            let mut current_bit = 0;
-            let mut discrepancy_marker = 0;
-    
-            // Send Search ROM command
-            self.wire.write_bytes(&[0xF0]).await;
-    
+            let mut last_zero = 0;      // Bit number of the last 0 branch taken on this pass
+
+            // Send the search command
+            if !self.wire.reset().await? {
+                return Err(SensorError::NoPresence);
+            }
+            self.wire.write_bytes(&[command]).await?;
+
             // Read all 64 bits
-            for byte in 0..8 {
-                for bit in 0..8 {
-                    // Read two bits
-                    let mut bit_buffer = [0u8; 1];
-                    self.wire.read_bytes(&mut bit_buffer).await;
-                    let id_bit = (bit_buffer[0] & 0x01) != 0;
-                    self.wire.read_bytes(&mut bit_buffer).await;
-                    let complement_bit = (bit_buffer[0] & 0x01) != 0;
-    
-                    // Process bit results
-                    if !id_bit && !complement_bit {
-                        if current_bit == last_discrepancy {
-                            rom_bits[current_bit] = true;
-                        } else if current_bit > last_discrepancy {
-                            rom_bits[current_bit] = false;
-                            discrepancy_marker = current_bit;
-                        }
-                        // Remove self-assignment, the value stays unchanged in the else case
-                    } else if id_bit && !complement_bit {
-                        rom_bits[current_bit] = true;
-                    } else if !id_bit && complement_bit {
-                        rom_bits[current_bit] = false;
-                    } else {
-                        // Invalid response
-                        return Err(());
-                    }
-    
-                    // Write the bit
-                    let write_bit = if rom_bits[current_bit] { 1u8 } else { 0u8 };
-                    self.wire.write_bytes(&[write_bit]).await;
-    
-                    // Store bit in current_rom
-                    if rom_bits[current_bit] {
-                        current_rom[byte] |= 1 << bit;
+            for id_bit_number in 1..=64 {
+                // Where devices differ, take the 1 branch at the last discrepancy, the 0 branch past it and the same branch as last time before it
+                let direction = if id_bit_number < last_discrepancy {
+                    rom_bits[id_bit_number - 1]
+                } else {
+                    id_bit_number == last_discrepancy
+                };
+                let triplet = self.wire.triplet(direction).await?;
+                if triplet.id_bit && triplet.complement_bit {
+                    if id_bit_number == 1 && device_count == 0 {
+                        return Ok(devices);  // Devices are present but none of them match (no device is in alarm)
                     }
-    
-                    current_bit += 1;
+                    return Err(SensorError::NoPresence);  // A device stopped answering part way through
+                }
+                if !triplet.id_bit && !triplet.complement_bit && !triplet.direction {
+                    last_zero = id_bit_number;
+                }
+                rom_bits[id_bit_number - 1] = triplet.direction;
+
+                // Store bit in current_rom
+                if triplet.direction {
+                    current_rom[(id_bit_number - 1) / 8] |= 1 << ((id_bit_number - 1) % 8);
                 }
             }
 
@@ -82,53 +400,203 @@ impl<'d, PIO: pio::Instance, const SM: usize> Ds18b20<'d, PIO, SM> {
                 device_count += 1;
             }
 
-            last_discrepancy = discrepancy_marker;
+            last_discrepancy = last_zero;
             if last_discrepancy == 0 || device_count >= 8 {
-                break 'search;
+                return Ok(devices);
             }
         }
+    }
 
-        Ok(devices)
+    /// Set the resolution for a specific device, keeping its alarm limits
+    pub async fn set_resolution_with_rom(&mut self, rom: &[u8; 8], resolution: Resolution) -> Result<(), SensorError> {
+        let config = SensorConfig { resolution, ..self.read_config_with_rom(rom).await? };
+        self.write_config_with_rom(rom, config).await
     }
 
-    /// Set the resolution for a specific device
-    pub async fn set_resolution_with_rom(&mut self, rom: &[u8; 8], resolution: Resolution) -> Result<(), ()> {
-        // Match ROM command followed by ROM code
-        self.wire.write_bytes(&[0x55]).await;
-        self.wire.write_bytes(rom).await;
+    /// Set the resolution for all devices (broadcast), keeping the alarm limits last written
+    pub async fn set_resolution(&mut self, resolution: Resolution) -> Result<(), SensorError> {
+        let config = SensorConfig { resolution, ..self.config };
+        self.write_config(config).await
+    }
 
-        // Write to configuration register
-        self.wire.write_bytes(&[0x4E]).await;               // Write Scratchpad
-        self.wire.write_bytes(&[0x00]).await;               // Th register
-        self.wire.write_bytes(&[0x00]).await;               // Tl register
-        self.wire.write_bytes(&[resolution as u8]).await;   // Configuration register
+    /// Set the TH/TL alarm limits for a specific device, keeping its resolution
+    pub async fn set_alarm_limits_with_rom(&mut self, rom: &[u8; 8], alarm_low: i8, alarm_high: i8) -> Result<(), SensorError> {
+        let config = SensorConfig { alarm_low, alarm_high, ..self.read_config_with_rom(rom).await? };
+        self.write_config_with_rom(rom, config).await
+    }
 
-        // Read back scratchpad to verify
-        self.wire.write_bytes(&[0xBE]).await;
-        let mut data = [0; 9];
-        self.wire.read_bytes(&mut data).await;
-        
-        if Self::crc8(&data) == 0 && (data[4] & 0x60) == (resolution as u8 & 0x60) {
+    /// Set the TH/TL alarm limits for all devices (broadcast), keeping the resolution last written
+    pub async fn set_alarm_limits(&mut self, alarm_low: i8, alarm_high: i8) -> Result<(), SensorError> {
+        let config = SensorConfig { alarm_low, alarm_high, ..self.config };
+        self.write_config(config).await
+    }
+
+    /// Write the configuration to the scratchpad of a specific device and read it back to verify
+    pub async fn write_config_with_rom(&mut self, rom: &[u8; 8], config: SensorConfig) -> Result<(), SensorError> {
+        self.send_command(Some(rom), &Self::write_scratchpad_command(config)).await?;
+        if self.read_config_with_rom(rom).await? == config {
             Ok(())
         } else {
-            Err(())
+            Err(SensorError::ConfigMismatch)
+        }
+    }
+
+    /// Write the configuration to the scratchpad of all devices (broadcast) and read each device back to verify
+    pub async fn write_config(&mut self, config: SensorConfig) -> Result<(), SensorError> {
+        self.send_command(None, &Self::write_scratchpad_command(config)).await?;
+        self.config = config;
+        // A broadcast read would collide when there is more than one device, so each one is read back by its ROM code
+        let devices = self.search_for_roms().await?;
+        for rom in devices.iter().flatten().filter(|rom| rom[0] == FAMILY_CODE) {
+            if self.read_config_with_rom(rom).await? != config {
+                return Err(SensorError::ConfigMismatch);
+            }
         }
+        Ok(())
     }
 
-    /// Set the resolution for all devices (broadcast)
-    pub async fn set_resolution(&mut self, resolution: Resolution) -> Result<(), ()> {
-        // Skip ROM command (broadcast to all devices)
-        self.wire.write_bytes(&[0xCC]).await;
+    /// Read the configuration from the scratchpad of a specific device
+    pub async fn read_config_with_rom(&mut self, rom: &[u8; 8]) -> Result<SensorConfig, SensorError> {
+        let data = self.read_scratchpad(Some(rom)).await?;
+        Ok(Self::decode_config(&data))
+    }
 
-        // Write to configuration register
-        self.wire.write_bytes(&[0x4E]).await;               // Write Scratchpad
-        self.wire.write_bytes(&[0x00]).await;               // Th register
-        self.wire.write_bytes(&[0x00]).await;               // Tl register
-        self.wire.write_bytes(&[resolution as u8]).await;   // Configuration register
+    /// Read the configuration from the scratchpad. (Only works if there is one device)
+    pub async fn read_config(&mut self) -> Result<SensorConfig, SensorError> {
+        let data = self.read_scratchpad(None).await?;
+        Ok(Self::decode_config(&data))
+    }
+
+    /// Save the scratchpad of a specific device (TH, TL and configuration) to its EEPROM so it survives a power cycle
+    pub async fn copy_scratchpad_with_rom(&mut self, rom: &[u8; 8]) -> Result<(), SensorError> {
+        self.send_command(Some(rom), &[0x48]).await?;
+        self.hold_bus(Duration::from_millis(EEPROM_WRITE_MS)).await;
+        Ok(())
+    }
 
+    /// Save the scratchpad of all devices to their EEPROM (broadcast)
+    pub async fn copy_scratchpad(&mut self) -> Result<(), SensorError> {
+        self.send_command(None, &[0x48]).await?;
+        self.hold_bus(Duration::from_millis(EEPROM_WRITE_MS)).await;
         Ok(())
     }
 
+    /// Reload the scratchpad of a specific device from its EEPROM
+    pub async fn recall_eeprom_with_rom(&mut self, rom: &[u8; 8]) -> Result<SensorConfig, SensorError> {
+        self.send_command(Some(rom), &[0xB8]).await?;
+        self.wait_for_recall().await;
+        self.read_config_with_rom(rom).await
+    }
+
+    /// Reload the scratchpad of all devices from their EEPROM (broadcast)
+    pub async fn recall_eeprom(&mut self) {
+        let _ = self.send_command(None, &[0xB8]).await;
+        self.wait_for_recall().await;
+    }
+
+    /// Ask a specific device whether it is externally powered or running on parasite power
+    pub async fn read_power_supply_with_rom(&mut self, rom: &[u8; 8]) -> Result<PowerSupply, SensorError> {
+        self.send_command(Some(rom), &[0xB4]).await?;
+        self.read_power_supply_slots().await
+    }
+
+    /// Check whether any device on the bus is running on parasite power (broadcast)
+    pub async fn read_power_supply(&mut self) -> Result<PowerSupply, SensorError> {
+        self.send_command(None, &[0xB4]).await?;
+        self.read_power_supply_slots().await
+    }
+
+    /// Parasite powered devices pull the bus low during the read slots after Read Power Supply.
+    /// `send_command` has already checked for a presence pulse, as an empty bus would also read back as all 1s.
+    async fn read_power_supply_slots(&mut self) -> Result<PowerSupply, SensorError> {
+        let mut slots = [0u8; 1];
+        self.wire.read_bytes(&mut slots).await?;
+        if slots[0] == 0xFF {
+            Ok(PowerSupply::External)
+        } else {
+            Ok(PowerSupply::Parasite)
+        }
+    }
+
+    /// Wait while a parasite powered device is busy, with the strong pull-up on if there is one.
+    /// The command before this has already finished on the wire (see `OneWire::write_bytes`), so the pull-up can't
+    /// fight the last bits of it and comes on within the 10us the datasheet allows.
+    async fn hold_bus(&mut self, duration: Duration) {
+        let pullup = match self.power_supply {
+            PowerSupply::Parasite => self.strong_pullup.as_mut(),
+            PowerSupply::External => None,
+        };
+        match pullup {
+            Some(pin) => {
+                let _ = pin.set_high();
+                Timer::after(duration).await;
+                let _ = pin.set_low();
+            }
+            None => Timer::after(duration).await,
+        }
+    }
+
+    /// The device sends 0s in read slots while it is recalling from EEPROM and 1s when it is done
+    async fn wait_for_recall(&mut self) {
+        for _ in 0..10 {
+            let mut slots = [0u8; 1];
+            let _ = self.wire.read_bytes(&mut slots).await;
+            if slots[0] != 0 {
+                return;
+            }
+            Timer::after_millis(1).await;
+        }
+    }
+
+    /// Reset the bus and send a command to a specific device (`Some(rom)`) or all devices (`None`)
+    async fn send_command(&mut self, rom: Option<&[u8; 8]>, command: &[u8]) -> Result<(), SensorError> {
+        if !self.wire.reset().await? {
+            return Err(SensorError::NoPresence);
+        }
+        let mut buffer = [0u8; 16];
+        let start = match rom {
+            Some(rom) => {
+                buffer[0] = 0x55;   // Match ROM
+                buffer[1..9].copy_from_slice(rom);
+                9
+            }
+            None => {
+                buffer[0] = 0xCC;   // Skip ROM
+                1
+            }
+        };
+        let end = start + command.len();
+        buffer[start..end].copy_from_slice(command);
+        self.wire.write_bytes(&buffer[..end]).await
+    }
+
+    /// Read the scratchpad of a specific device (`Some(rom)`) or the only device (`None`)
+    async fn read_scratchpad(&mut self, rom: Option<&[u8; 8]>) -> Result<[u8; 9], SensorError> {
+        self.send_command(rom, &[0xBE]).await?;
+        let mut data = [0; 9];
+        self.wire.read_bytes(&mut data).await?;
+        let missing = match rom {
+            Some(_) => SensorError::RomNotFound,
+            None => SensorError::NoPresence,
+        };
+        Self::check_scratchpad(&data, missing)?;
+        Ok(data)
+    }
+
+    /// Build the Write Scratchpad command for the configuration
+    fn write_scratchpad_command(config: SensorConfig) -> [u8; 4] {
+        [0x4E, config.alarm_high as u8, config.alarm_low as u8, config.resolution as u8]
+    }
+
+    /// Decode the configuration from a scratchpad read
+    fn decode_config(data: &[u8; 9]) -> SensorConfig {
+        SensorConfig {
+            alarm_high: data[2] as i8,
+            alarm_low: data[3] as i8,
+            resolution: Resolution::from_config(data[4]),
+        }
+    }
+
     /// Read the unique 64-bit ROM code of the sensor.
     /// This should only be used when there is a single device on the bus.
     /// Returns an array of 8 bytes: [family_code, serial(6), crc]
@@ -165,6 +633,32 @@ impl<'d, PIO: pio::Instance, const SM: usize> Ds18b20<'d, PIO, SM> {
         hex
     }
 
+    /// Check a scratchpad read for a missing device, a shorted bus or a bad CRC.
+    /// `missing` is the error to report when nothing answered.
+    fn check_scratchpad(data: &[u8; 9], missing: SensorError) -> Result<(), SensorError> {
+        if data.iter().all(|b| *b == 0xFF) {
+            Err(missing)
+        } else if data.iter().all(|b| *b == 0x00) {
+            Err(SensorError::BusShorted)   // Checked before the CRC as all zeros has a valid CRC
+        } else if Self::crc8(data) != 0 {
+            Err(SensorError::CrcMismatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decode the temperature from a checked scratchpad read
+    fn decode_temperature(data: &[u8; 9]) -> Result<f32, SensorError> {
+        let raw = i16::from_le_bytes([data[0], data[1]]);
+        // 85 degrees C is also a real reading, but a finished conversion rewrites the reserved byte 6 (0x10 - the low
+        // nibble of the reading), so the power-on value only counts when byte 6 still holds its power-on default
+        if raw == POWER_ON_RESET_VALUE && data[6] == POWER_ON_RESERVED {
+            Err(SensorError::PowerOnReset)
+        } else {
+            Ok(raw as f32 / 16.)
+        }
+    }
+
     /// Calculate CRC8 of the data
     fn crc8(data: &[u8]) -> u8 {
         let mut temp;
@@ -184,43 +678,177 @@ impl<'d, PIO: pio::Instance, const SM: usize> Ds18b20<'d, PIO, SM> {
         crc
     }
 
-    /// Start a new measurement for a specific device. Allow at least 1000ms before getting `temperature`.
+    /// Wait for a conversion started with `start` or `start_with_rom` to finish.
+    /// Externally powered sensors are polled with read slots and return early. Otherwise this waits the full conversion time for the resolution.
+    pub async fn wait_for_conversion(&mut self) {
+        let conversion_time = self.config.resolution.conversion_time();
+        if self.power_supply == PowerSupply::Parasite {
+            self.hold_bus(conversion_time).await;
+            return;
+        }
+        let deadline = Instant::now() + conversion_time;
+        while Instant::now() < deadline {
+            // The sensor holds the bus low during read slots until the conversion is complete
+            let mut slots = [0u8; 1];
+            let _ = self.wire.read_bytes(&mut slots).await;
+            if slots[0] != 0 {
+                return;
+            }
+            Timer::after_millis(CONVERSION_POLL_MS).await;
+        }
+    }
+
+    /// Start a new measurement for a specific device. Call `wait_for_conversion` before getting `temperature`.
     pub async fn start_with_rom(&mut self, rom: &[u8; 8]) {
-        // Match ROM command followed by ROM code
-        self.wire.write_bytes(&[0x55]).await;
-        self.wire.write_bytes(rom).await;
-        // Start conversion
-        self.wire.write_bytes(&[0x44]).await;
+        let _ = self.send_command(Some(rom), &[0x44]).await;
     }
 
-    /// Start a new measurement for all devices. Allow at least 1000ms before getting `temperature`.
+    /// Start a new measurement for all devices. Call `wait_for_conversion` before getting `temperature`.
     pub async fn start(&mut self) {
-        self.wire.write_bytes(&[0xCC, 0x44]).await;
+        let _ = self.send_command(None, &[0x44]).await;
     }
 
-    /// Read the temperature from a specific device. Ensure >1000ms has passed since `start` before calling this.
-    pub async fn temperature_with_rom(&mut self, rom: &[u8; 8]) -> Result<f32, ()> {
-        // Match ROM command followed by ROM code
-        self.wire.write_bytes(&[0x55]).await;
-        self.wire.write_bytes(rom).await;
-        // Read scratchpad
-        self.wire.write_bytes(&[0xBE]).await;
-        let mut data = [0; 9];
-        self.wire.read_bytes(&mut data).await;
-        match Self::crc8(&data) == 0 {
-            true => Ok(((data[1] as u32) << 8 | data[0] as u32) as f32 / 16.),
-            false => Err(()),
-        }
+    /// Read the temperature from a specific device. Ensure the conversion has finished before calling this.
+    pub async fn temperature_with_rom(&mut self, rom: &[u8; 8]) -> Result<f32, SensorError> {
+        let data = self.read_scratchpad(Some(rom)).await?;
+        Ok(Self::decode_temperature(&data)? + self.offset(rom))
     }
 
-    /// Read the temperature. (Only works if there is one device) Ensure >1000ms has passed since `start` before calling this.
-    pub async fn temperature(&mut self) -> Result<f32, ()> {
-        self.wire.write_bytes(&[0xCC, 0xBE]).await;
-        let mut data = [0; 9];
-        self.wire.read_bytes(&mut data).await;
-        match Self::crc8(&data) == 0 {
-            true => Ok(((data[1] as u32) << 8 | data[0] as u32) as f32 / 16.),
-            false => Err(()),
+    /// Read the temperature. (Only works if there is one device) Ensure the conversion has finished before calling this.
+    /// No calibration is applied as the device isn't known, use `temperature_with_rom` for that.
+    pub async fn temperature(&mut self) -> Result<f32, SensorError> {
+        let data = self.read_scratchpad(None).await?;
+        Self::decode_temperature(&data)
+    }
+}
+
+impl<W: OneWire, P: OutputPin> TemperatureSensor for Ds18b20<W, P> {
+    async fn read_temperature(&mut self) -> Result<f32, SensorError> {
+        self.start().await;                 // Start a new measurement
+        self.wait_for_conversion().await;   // Wait for the measurement to finish (depends on the resolution)
+        match self.probe {
+            Some(rom) => self.temperature_with_rom(&rom).await,
+            None => self.temperature().await,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockOneWire, MockPin};
+    use embassy_futures::block_on;
+    use std::vec::Vec;
+
+    type TestSensor = Ds18b20<MockOneWire, MockPin>;
+
+    /// A ROM code with a valid CRC
+    fn rom(family: u8, serial: u8) -> [u8; 8] {
+        let mut rom = [family, serial, 0x12, 0x34, 0x56, 0x00, 0x00, 0x00];
+        rom[7] = TestSensor::crc8(&rom[0..7]);
+        rom
+    }
+
+    fn found(devices: [Option<[u8; 8]>; 8]) -> Vec<[u8; 8]> {
+        let mut roms: Vec<[u8; 8]> = devices.iter().flatten().copied().collect();
+        roms.sort();
+        roms
+    }
+
+    #[test]
+    fn search_finds_every_device() {
+        let mut roms = vec![rom(0x28, 0x01), rom(0x28, 0x02), rom(0x28, 0x80), rom(0x28, 0xFF), rom(0x3A, 0x01)];
+        let mut sensor = TestSensor::new(MockOneWire::new(&roms));
+        let devices = block_on(sensor.search_for_roms()).unwrap();
+        roms.sort();
+        assert_eq!(found(devices), roms);
+    }
+
+    #[test]
+    fn search_finds_devices_that_differ_at_the_first_bit() {
+        // 0x28 and 0x29 only differ at the first bit, where a 0-based last discrepancy reads as no discrepancy at all
+        let roms = [rom(0x28, 0x01), rom(0x29, 0x01)];
+        let mut sensor = TestSensor::new(MockOneWire::new(&roms));
+        let devices = block_on(sensor.search_for_roms()).unwrap();
+        assert_eq!(found(devices), roms);
+    }
+
+    #[test]
+    fn search_stops_at_eight_devices() {
+        let roms: Vec<[u8; 8]> = (0..10).map(|serial| rom(0x28, serial)).collect();
+        let mut sensor = TestSensor::new(MockOneWire::new(&roms));
+        let devices = block_on(sensor.search_for_roms()).unwrap();
+        assert_eq!(found(devices).len(), 8);
+    }
+
+    #[test]
+    fn search_of_an_empty_bus_is_no_presence() {
+        let mut sensor = TestSensor::new(MockOneWire::new(&[]));
+        assert_eq!(block_on(sensor.search_for_roms()).err(), Some(SensorError::NoPresence));
+    }
+
+    #[test]
+    fn alarm_search_with_no_device_in_alarm_is_empty() {
+        let mut sensor = TestSensor::new(MockOneWire::new(&[rom(0x28, 0x01), rom(0x28, 0x02)]));
+        let devices = block_on(sensor.alarm_search()).unwrap();
+        assert!(found(devices).is_empty());
+    }
+
+    #[test]
+    fn alarm_search_finds_only_devices_in_alarm() {
+        let mut wire = MockOneWire::new(&[rom(0x28, 0x01), rom(0x28, 0x02), rom(0x28, 0x03)]);
+        wire.alarmed = vec![rom(0x28, 0x02)];
+        let mut sensor = TestSensor::new(wire);
+        let devices = block_on(sensor.alarm_search()).unwrap();
+        assert_eq!(found(devices), [rom(0x28, 0x02)]);
+        assert_eq!(sensor.wire.writes[0], [0xEC]);
+    }
+
+    #[test]
+    fn temperature_alarm_ignores_switches_answering_the_alarm_search() {
+        let mut wire = MockOneWire::new(&[rom(0x28, 0x01), rom(0x29, 0x01)]);
+        wire.alarmed = vec![rom(0x29, 0x01)];     // A DS2408 whose conditional search matched
+        let mut sensor = TestSensor::new(wire);
+        assert_eq!(block_on(sensor.temperature_alarm()), Ok(false));
+        sensor.wire.alarmed.push(rom(0x28, 0x01));
+        assert_eq!(block_on(sensor.temperature_alarm()), Ok(true));
+    }
+
+    #[test]
+    fn probe_is_kept_while_it_is_present() {
+        let mut roster = SensorRoster::new();
+        roster.update(&[Some(rom(0x28, 0x80)), None, None, None, None, None, None, None]);
+        let probe = roster.choose_probe(None);
+        assert_eq!(probe, Some(rom(0x28, 0x80)));
+        // A second probe that comes first in search order doesn't take over
+        roster.update(&[Some(rom(0x28, 0x01)), Some(rom(0x28, 0x80)), None, None, None, None, None, None]);
+        assert_eq!(roster.choose_probe(probe), probe);
+    }
+
+    #[test]
+    fn new_probe_is_chosen_when_the_current_one_is_removed() {
+        let mut roster = SensorRoster::new();
+        roster.update(&[Some(rom(0x29, 0x01)), Some(rom(0x28, 0x02)), None, None, None, None, None, None]);
+        assert_eq!(roster.choose_probe(Some(rom(0x28, 0x80))), Some(rom(0x28, 0x02)));
+        roster.update(&[Some(rom(0x29, 0x01)), None, None, None, None, None, None, None]);
+        assert_eq!(roster.choose_probe(Some(rom(0x28, 0x02))), None);
+    }
+
+    #[test]
+    fn copy_scratchpad_addresses_the_device_and_reports_a_missing_bus() {
+        let mut sensor = TestSensor::new(MockOneWire::new(&[rom(0x28, 0x01)]));
+        assert_eq!(block_on(sensor.copy_scratchpad_with_rom(&rom(0x28, 0x01))), Ok(()));
+        assert_eq!(sensor.wire.writes.last().unwrap().last(), Some(&0x48));
+        let mut sensor = TestSensor::new(MockOneWire::new(&[]));
+        assert_eq!(block_on(sensor.copy_scratchpad_with_rom(&rom(0x28, 0x01))), Err(SensorError::NoPresence));
+        assert_eq!(block_on(sensor.copy_scratchpad()), Err(SensorError::NoPresence));
+    }
+
+    #[test]
+    fn device_lost_part_way_through_a_search_is_no_presence() {
+        let mut wire = MockOneWire::new(&[rom(0x28, 0x01)]);
+        wire.unplug_after = Some(20);
+        let mut sensor = TestSensor::new(wire);
+        assert_eq!(block_on(sensor.search_for_roms()).err(), Some(SensorError::NoPresence));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::temperature::TemperatureUnit;
+
+pub const SETTINGS_VERSION: u32 = 3;        // Layout written by `Settings::to_bytes`
+pub const SETTINGS_SIZE: usize = 24;
+const SETTINGS_UNVERSIONED: u32 = 0xFFFF_FFFF;  // Erased flash after the target, saved by firmware before the layout was versioned
+
+/// Settings kept in flash across power cycles.
+/// Every layout starts with the target (f32) and then the layout version (u32), both little endian.
+/// Version 2 adds the temperature unit's index in `TemperatureUnit::ALL` (u8).
+/// Version 3 adds the scale's tare offset (i32) and counts per gram (f32) at byte 12, then the start weight (f32).
+/// Values that haven't been set are left as erased flash (all 1s).
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct Settings {
+    pub target: f32,                // Degrees C, whatever the unit
+    pub unit: TemperatureUnit,
+    pub scale: Option<ScaleCalibration>,    // None until the scale has been tared or calibrated from the menu
+    pub start_weight: Option<f32>,  // Fermenter weight at pitching (grams), CO2 loss is measured from it
+}
+
+/// Tare and scale factor of the fermenter scale, see `ScaleConfig`
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub struct ScaleCalibration {
+    pub offset: i32,
+    pub counts_per_gram: f32,
+}
+
+/// What was found in flash
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Loaded {
+    Current(Settings),
+    Migrated(Settings),     // Saved in an older layout, to be saved again in the current one
+    Invalid,                // Blank, corrupt or saved by newer firmware
+}
+
+impl Settings {
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0xFF; SETTINGS_SIZE];
+        bytes[0..4].copy_from_slice(&self.target.to_le_bytes());
+        bytes[4..8].copy_from_slice(&SETTINGS_VERSION.to_le_bytes());
+        bytes[8] = self.unit.index() as u8;
+        if let Some(scale) = self.scale {
+            bytes[12..16].copy_from_slice(&scale.offset.to_le_bytes());
+            bytes[16..20].copy_from_slice(&scale.counts_per_gram.to_le_bytes());
+        }
+        if let Some(weight) = self.start_weight {
+            bytes[20..24].copy_from_slice(&weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Read settings saved by this or older firmware. Layouts from before the unit was saved take `default_unit`,
+    /// and layouts from before the scale was saved leave it uncalibrated.
+    pub fn from_bytes(bytes: &[u8; SETTINGS_SIZE], default_unit: TemperatureUnit) -> Loaded {
+        let word = |at: usize| -> [u8; 4] { bytes[at..at + 4].try_into().unwrap() };
+        let target = f32::from_le_bytes(word(0));
+        let version = u32::from_le_bytes(word(4));
+        if !target.is_finite() {
+            return Loaded::Invalid;
+        }
+        let unit = TemperatureUnit::ALL.get(bytes[8] as usize).copied();
+        match version {
+            SETTINGS_VERSION => {
+                let Some(unit) = unit else {
+                    return Loaded::Invalid;
+                };
+                let counts_per_gram = f32::from_le_bytes(word(16));
+                let scale = (counts_per_gram.is_finite() && counts_per_gram != 0.0)
+                    .then(|| ScaleCalibration { offset: i32::from_le_bytes(word(12)), counts_per_gram });
+                let start_weight = Some(f32::from_le_bytes(word(20))).filter(|weight| weight.is_finite());
+                Loaded::Current(Settings { target, unit, scale, start_weight })
+            }
+            2 => match unit {
+                Some(unit) => Loaded::Migrated(Settings { target, unit, scale: None, start_weight: None }),
+                None => Loaded::Invalid,
+            },
+            1 | SETTINGS_UNVERSIONED => Loaded::Migrated(Settings { target, unit: default_unit, scale: None, start_weight: None }),
+            _ => Loaded::Invalid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: ScaleCalibration = ScaleCalibration { offset: -8_400, counts_per_gram: 21.5 };
+
+    /// A record as written by older firmware: the target, then the version if it had one, then erased flash
+    fn old_record(target: f32, version: Option<u32>) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0xFF; SETTINGS_SIZE];
+        bytes[0..4].copy_from_slice(&target.to_le_bytes());
+        if let Some(version) = version {
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// A version 2 record, which added the unit
+    fn version_2_record(target: f32, unit: u8) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = old_record(target, Some(2));
+        bytes[8] = unit;
+        bytes
+    }
+
+    fn settings(target: f32, unit: TemperatureUnit) -> Settings {
+        Settings { target, unit, scale: None, start_weight: None }
+    }
+
+    #[test]
+    fn settings_read_back_as_saved() {
+        let saved = Settings { scale: Some(SCALE), start_weight: Some(24_350.0), ..settings(18.5, TemperatureUnit::Fahrenheit) };
+        assert_eq!(Settings::from_bytes(&saved.to_bytes(), TemperatureUnit::Celsius), Loaded::Current(saved));
+        let saved = settings(18.5, TemperatureUnit::Celsius);
+        assert_eq!(Settings::from_bytes(&saved.to_bytes(), TemperatureUnit::Fahrenheit), Loaded::Current(saved));
+    }
+
+    #[test]
+    fn version_2_is_migrated_keeping_the_unit() {
+        let loaded = Settings::from_bytes(&version_2_record(20.0, 1), TemperatureUnit::Celsius);
+        assert_eq!(loaded, Loaded::Migrated(settings(20.0, TemperatureUnit::Fahrenheit)));
+    }
+
+    #[test]
+    fn version_1_is_migrated_with_the_default_unit() {
+        let loaded = Settings::from_bytes(&old_record(20.0, Some(1)), TemperatureUnit::Fahrenheit);
+        assert_eq!(loaded, Loaded::Migrated(settings(20.0, TemperatureUnit::Fahrenheit)));
+    }
+
+    #[test]
+    fn unversioned_record_is_migrated_with_the_default_unit() {
+        let loaded = Settings::from_bytes(&old_record(19.0, None), TemperatureUnit::Celsius);
+        assert_eq!(loaded, Loaded::Migrated(settings(19.0, TemperatureUnit::Celsius)));
+    }
+
+    #[test]
+    fn migrated_settings_are_saved_in_the_current_layout() {
+        let Loaded::Migrated(settings) = Settings::from_bytes(&old_record(19.0, Some(1)), TemperatureUnit::Celsius) else {
+            panic!("version 1 wasn't migrated");
+        };
+        assert_eq!(Settings::from_bytes(&settings.to_bytes(), TemperatureUnit::Fahrenheit), Loaded::Current(settings));
+    }
+
+    #[test]
+    fn blank_newer_or_corrupt_records_are_invalid() {
+        assert_eq!(Settings::from_bytes(&[0xFF; SETTINGS_SIZE], TemperatureUnit::Celsius), Loaded::Invalid);
+        assert_eq!(Settings::from_bytes(&old_record(19.0, Some(4)), TemperatureUnit::Celsius), Loaded::Invalid);
+        assert_eq!(Settings::from_bytes(&version_2_record(19.0, 7), TemperatureUnit::Celsius), Loaded::Invalid);
+        let mut bytes = settings(19.0, TemperatureUnit::Celsius).to_bytes();
+        bytes[8] = 7;
+        assert_eq!(Settings::from_bytes(&bytes, TemperatureUnit::Celsius), Loaded::Invalid);
+    }
+}
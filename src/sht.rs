@@ -0,0 +1,188 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+#[allow(unused_imports)]   // The host tests link std, which has these maths functions built in
+use micromath::F32Ext;
+
+use crate::sensor::SensorError;
+
+pub const DEFAULT_ADDRESS: u8 = 0x44;   // ADDR pin low (0x45 when high on the SHT3x)
+
+// Magnus formula constants for dew point over water (-45 to 60 degrees C)
+const MAGNUS_B: f32 = 17.62;
+const MAGNUS_C: f32 = 243.12;
+
+/// Sensirion sensor family, they differ in commands and humidity scaling
+#[derive(Copy, Clone, PartialEq)]
+pub enum ShtModel {
+    Sht3x,
+    Sht4x,
+}
+
+impl ShtModel {
+    /// Single shot, high repeatability measurement command
+    fn measure_command(&self) -> &'static [u8] {
+        match self {
+            ShtModel::Sht3x => &[0x24, 0x00],   // No clock stretching
+            ShtModel::Sht4x => &[0xFD],
+        }
+    }
+
+    /// Time the sensor needs to finish a high repeatability measurement
+    fn measure_time_ms(&self) -> u32 {
+        match self {
+            ShtModel::Sht3x => 16,
+            ShtModel::Sht4x => 10,
+        }
+    }
+}
+
+/// A temperature and relative humidity measurement
+#[derive(Copy, Clone)]
+pub struct HumidityReading {
+    pub temperature: f32,   // Degrees C
+    pub humidity: f32,      // Relative humidity (%)
+}
+
+impl HumidityReading {
+    /// Temperature at which condensation forms (degrees C)
+    pub fn dew_point(&self) -> f32 {
+        let gamma = (self.humidity.max(0.01) / 100.0).ln() + MAGNUS_B * self.temperature / (MAGNUS_C + self.temperature);
+        MAGNUS_C * gamma / (MAGNUS_B - gamma)
+    }
+}
+
+/// Sensirion SHT3x/SHT4x humidity sensor driver
+pub struct Sht<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    address: u8,
+    model: ShtModel,
+}
+
+impl<I2C, D> Sht<I2C, D>
+where
+    I2C: I2c,
+    D: DelayNs,
+{
+    pub fn new(i2c: I2C, delay: D, address: u8, model: ShtModel) -> Self {
+        Self { i2c, delay, address, model }
+    }
+
+    /// Take a single measurement
+    pub async fn measure(&mut self) -> Result<HumidityReading, SensorError> {
+        self.i2c.write(self.address, self.model.measure_command()).await.map_err(|_| SensorError::NoPresence)?;
+        self.delay.delay_ms(self.model.measure_time_ms()).await;
+
+        // Temperature and humidity words, each followed by their CRC
+        let mut data = [0u8; 6];
+        self.i2c.read(self.address, &mut data).await.map_err(|_| SensorError::BusError)?;
+        let raw_temp = Self::checked_word(&data[0..3])? as f32;
+        let raw_humidity = Self::checked_word(&data[3..6])? as f32;
+
+        let temperature = -45.0 + 175.0 * raw_temp / 65535.0;
+        let humidity = match self.model {
+            ShtModel::Sht3x => 100.0 * raw_humidity / 65535.0,
+            ShtModel::Sht4x => -6.0 + 125.0 * raw_humidity / 65535.0,
+        };
+        Ok(HumidityReading { temperature, humidity: humidity.clamp(0.0, 100.0) })
+    }
+
+    /// Check the CRC on a data word from the sensor
+    fn checked_word(data: &[u8]) -> Result<u16, SensorError> {
+        if Self::crc8(&data[0..2]) == data[2] {
+            Ok(u16::from_be_bytes([data[0], data[1]]))
+        } else {
+            Err(SensorError::CrcMismatch)
+        }
+    }
+
+    /// Sensirion CRC-8 (polynomial 0x31, initial value 0xFF)
+    fn crc8(data: &[u8]) -> u8 {
+        let mut crc: u8 = 0xFF;
+        for b in data {
+            crc ^= b;
+            for _ in 0..8 {
+                crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 };
+            }
+        }
+        crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockI2c};
+    use embassy_futures::block_on;
+
+    type TestSht = Sht<MockI2c, MockDelay>;
+
+    /// A data word as the sensor sends it, followed by its CRC
+    fn word(value: u16) -> [u8; 3] {
+        let [msb, lsb] = value.to_be_bytes();
+        [msb, lsb, TestSht::crc8(&[msb, lsb])]
+    }
+
+    /// Temperature word then humidity word
+    fn frame(temp: u16, humidity: u16) -> [u8; 6] {
+        let mut data = [0; 6];
+        data[0..3].copy_from_slice(&word(temp));
+        data[3..6].copy_from_slice(&word(humidity));
+        data
+    }
+
+    #[test]
+    fn crc_matches_the_datasheet_example() {
+        assert_eq!(TestSht::crc8(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(TestSht::crc8(&[0x00, 0x00]), 0x81);
+    }
+
+    #[test]
+    fn sht3x_sends_single_shot_command_and_waits() {
+        let mut sht = Sht::new(MockI2c::new(&[&frame(0x6666, 0x8000)]), MockDelay::default(), DEFAULT_ADDRESS, ShtModel::Sht3x);
+        block_on(sht.measure()).unwrap();
+        assert_eq!(sht.i2c.writes, [(0x44, vec![0x24, 0x00])]);
+        assert_eq!(sht.delay.total_ns, 16_000_000);
+    }
+
+    #[test]
+    fn sht4x_sends_single_shot_command_and_waits() {
+        let mut sht = Sht::new(MockI2c::new(&[&frame(0x6666, 0x8000)]), MockDelay::default(), 0x45, ShtModel::Sht4x);
+        block_on(sht.measure()).unwrap();
+        assert_eq!(sht.i2c.writes, [(0x45, vec![0xFD])]);
+        assert_eq!(sht.delay.total_ns, 10_000_000);
+    }
+
+    #[test]
+    fn sht3x_conversion() {
+        let mut sht = Sht::new(MockI2c::new(&[&frame(0x6666, 0x8000)]), MockDelay::default(), DEFAULT_ADDRESS, ShtModel::Sht3x);
+        let reading = block_on(sht.measure()).unwrap();
+        assert!((reading.temperature - 25.0).abs() < 0.01);
+        assert!((reading.humidity - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn sht4x_conversion_is_offset_and_clamped() {
+        let mut sht = Sht::new(MockI2c::new(&[&frame(0x6666, 0x8000), &frame(0x0000, 0x0000)]), MockDelay::default(), DEFAULT_ADDRESS, ShtModel::Sht4x);
+        let reading = block_on(sht.measure()).unwrap();
+        assert!((reading.temperature - 25.0).abs() < 0.01);
+        assert!((reading.humidity - 56.5).abs() < 0.01);
+        let reading = block_on(sht.measure()).unwrap();
+        assert!((reading.temperature + 45.0).abs() < 0.01);
+        assert_eq!(reading.humidity, 0.0);     // -6 %RH is clamped
+    }
+
+    #[test]
+    fn bad_crc_is_rejected() {
+        let mut data = frame(0x6666, 0x8000);
+        data[5] ^= 0x01;
+        let mut sht = Sht::new(MockI2c::new(&[&data]), MockDelay::default(), DEFAULT_ADDRESS, ShtModel::Sht3x);
+        assert_eq!(block_on(sht.measure()).err(), Some(SensorError::CrcMismatch));
+    }
+
+    #[test]
+    fn missing_sensor_is_reported() {
+        let mut sht = Sht::new(MockI2c::new(&[]), MockDelay::default(), DEFAULT_ADDRESS, ShtModel::Sht3x);
+        assert_eq!(block_on(sht.measure()).err(), Some(SensorError::BusError));
+    }
+}
@@ -0,0 +1,315 @@
+use heapless::Vec;
+
+use crate::onewire::OneWire;
+use crate::sensor::SensorError;
+
+const ACCESS_WRITE: u8 = 0x5A;      // PIO Access Write (DS2413) / Channel Access Write (DS2408)
+const ACCESS_READ: u8 = 0xF5;       // PIO Access Read (DS2413)
+const READ_REGISTERS: u8 = 0xF0;    // Read PIO Registers (DS2408)
+const DS2408_LATCH_REGISTER: u8 = 0x89;
+const WRITE_CONFIRMED: u8 = 0xAA;   // Sent by the switch after a write with a valid inverted data byte
+
+/// 1-Wire switch chips that can drive outputs
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum SwitchFamily {
+    Ds2413,     // Two open-drain channels
+    Ds2408,     // Eight open-drain channels
+}
+
+impl SwitchFamily {
+    pub const DS2413_CODE: u8 = 0x3A;
+    pub const DS2408_CODE: u8 = 0x29;
+
+    /// The switch family from the first byte of a ROM code, None if the device isn't a switch
+    pub fn from_rom(rom: &[u8; 8]) -> Option<Self> {
+        match rom[0] {
+            Self::DS2413_CODE => Some(SwitchFamily::Ds2413),
+            Self::DS2408_CODE => Some(SwitchFamily::Ds2408),
+            _ => None,
+        }
+    }
+
+    pub fn channels(&self) -> u8 {
+        match self {
+            SwitchFamily::Ds2413 => 2,
+            SwitchFamily::Ds2408 => 8,
+        }
+    }
+}
+
+/// What an output is used for
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+pub enum OutputRole {
+    Heater,
+    Cooler,
+    Fan,
+    Valve,
+}
+
+/// The state wanted for each output role
+#[derive(Copy, Clone, Default, PartialEq, defmt::Format)]
+pub struct RoleStates {
+    pub heater: bool,
+    pub cooler: bool,
+    pub fan: bool,
+    pub valve: bool,
+}
+
+impl RoleStates {
+    pub const fn new() -> Self {
+        Self { heater: false, cooler: false, fan: false, valve: false }
+    }
+
+    pub fn get(&self, role: OutputRole) -> bool {
+        match role {
+            OutputRole::Heater => self.heater,
+            OutputRole::Cooler => self.cooler,
+            OutputRole::Fan => self.fan,
+            OutputRole::Valve => self.valve,
+        }
+    }
+
+    /// Set the state for a role. Returns true if it changed.
+    pub fn set(&mut self, role: OutputRole, on: bool) -> bool {
+        let state = match role {
+            OutputRole::Heater => &mut self.heater,
+            OutputRole::Cooler => &mut self.cooler,
+            OutputRole::Fan => &mut self.fan,
+            OutputRole::Valve => &mut self.valve,
+        };
+        let changed = *state != on;
+        *state = on;
+        changed
+    }
+}
+
+/// Assigns a channel of a 1-Wire switch to a role
+#[derive(Copy, Clone)]
+pub struct RoleAssignment {
+    pub rom: Option<[u8; 8]>,   // ROM code of the switch, None for the first switch found on the bus
+    pub channel: u8,            // PIOA = 0, PIOB = 1 (DS2413) or P0 - P7 (DS2408)
+    pub role: OutputRole,
+}
+
+/// A DS2413 or DS2408 on the bus.
+/// An output is on when its open-drain transistor conducts, which is a 0 in the latch.
+#[derive(Copy, Clone)]
+pub struct Switch {
+    pub rom: [u8; 8],
+    pub family: SwitchFamily,
+}
+
+impl Switch {
+    /// The switch with this ROM code, None if it isn't a supported switch
+    pub fn from_rom(rom: &[u8; 8]) -> Option<Self> {
+        SwitchFamily::from_rom(rom).map(|family| Self { rom: *rom, family })
+    }
+
+    /// Set the output latches and read them back to check the switch took the new state
+    pub async fn write_latches<W: OneWire>(&self, wire: &mut W, latches: u8) -> Result<(), SensorError> {
+        let latches = match self.family {
+            SwitchFamily::Ds2413 => latches | 0xFC,    // Unused bits must be written as 1s
+            SwitchFamily::Ds2408 => latches,
+        };
+        self.send_command(wire, &[ACCESS_WRITE, latches, !latches]).await?;
+        let mut confirm = [0u8; 2];
+        wire.read_bytes(&mut confirm).await?;
+        if confirm[0] != WRITE_CONFIRMED {
+            return Err(SensorError::CrcMismatch);
+        }
+        if self.read_latches(wire).await? != latches & self.mask() {
+            return Err(SensorError::ConfigMismatch);
+        }
+        Ok(())
+    }
+
+    /// Read the output latches
+    pub async fn read_latches<W: OneWire>(&self, wire: &mut W) -> Result<u8, SensorError> {
+        let mut data = [0u8; 1];
+        match self.family {
+            SwitchFamily::Ds2413 => {
+                // Bit 1 is the PIOA latch and bit 3 the PIOB latch, the upper nibble is the complement of the lower
+                self.send_command(wire, &[ACCESS_READ]).await?;
+                wire.read_bytes(&mut data).await?;
+                if data[0] >> 4 != !data[0] & 0x0F {
+                    return Err(SensorError::CrcMismatch);
+                }
+                Ok(((data[0] >> 1) & 0x01) | ((data[0] >> 2) & 0x02))
+            }
+            SwitchFamily::Ds2408 => {
+                self.send_command(wire, &[READ_REGISTERS, DS2408_LATCH_REGISTER, 0x00]).await?;
+                wire.read_bytes(&mut data).await?;
+                Ok(data[0])
+            }
+        }
+    }
+
+    /// Bits used by the switch's channels
+    fn mask(&self) -> u8 {
+        match self.family {
+            SwitchFamily::Ds2413 => 0x03,
+            SwitchFamily::Ds2408 => 0xFF,
+        }
+    }
+
+    /// Reset the bus and address the switch
+    async fn send_command<W: OneWire>(&self, wire: &mut W, command: &[u8]) -> Result<(), SensorError> {
+        if !wire.reset().await? {
+            return Err(SensorError::NoPresence);
+        }
+        let mut buffer = [0u8; 12];
+        buffer[0] = 0x55;   // Match ROM
+        buffer[1..9].copy_from_slice(&self.rom);
+        let end = 9 + command.len();
+        buffer[9..end].copy_from_slice(command);
+        wire.write_bytes(&buffer[..end]).await
+    }
+}
+
+/// The 1-Wire switches on a bus and the roles assigned to their channels
+pub struct Actuators<'a> {
+    assignments: &'a [RoleAssignment],
+    switches: Vec<Switch, 8>,
+}
+
+impl<'a> Actuators<'a> {
+    pub fn new(assignments: &'a [RoleAssignment]) -> Self {
+        Self { assignments, switches: Vec::new() }
+    }
+
+    /// Keep the switches from the ROM codes of the devices found on the bus
+    pub fn discover(&mut self, roms: &[[u8; 8]]) {
+        self.switches = roms.iter().filter_map(Switch::from_rom).collect();
+    }
+
+    pub fn switches(&self) -> &[Switch] {
+        &self.switches
+    }
+
+    /// Switch every assigned output to the state wanted for its role.
+    /// Every switch is written even if one fails, and the last failure is returned.
+    pub async fn apply<W: OneWire>(&self, wire: &mut W, states: RoleStates) -> Result<(), SensorError> {
+        let mut result = Ok(());
+        for (index, switch) in self.switches.iter().enumerate() {
+            let mut latches = 0xFF;     // Everything off
+            let mut assigned = false;
+            for assignment in self.assignments.iter() {
+                let matches = match assignment.rom {
+                    Some(rom) => rom == switch.rom,
+                    None => index == 0,
+                };
+                if matches && assignment.channel < switch.family.channels() {
+                    assigned = true;
+                    if states.get(assignment.role) {
+                        latches &= !(1 << assignment.channel);
+                    }
+                }
+            }
+            if assigned {
+                if let Err(fault) = switch.write_latches(wire, latches).await {
+                    result = Err(fault);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockOneWire;
+    use embassy_futures::block_on;
+
+    const DS2413: [u8; 8] = [0x3A, 0x01, 0, 0, 0, 0, 0, 0];
+    const DS2408: [u8; 8] = [0x29, 0x02, 0, 0, 0, 0, 0, 0];
+
+    /// The bytes sent to address a switch and give it a command
+    fn frame(rom: &[u8; 8], command: &[u8]) -> std::vec::Vec<u8> {
+        let mut frame = vec![0x55];
+        frame.extend_from_slice(rom);
+        frame.extend_from_slice(command);
+        frame
+    }
+
+    /// A DS2413 PIO Access Read answer for the two latches, with the pins following them and the complement on top
+    fn ds2413_status(latches: u8) -> u8 {
+        let a = latches & 0x01;
+        let b = (latches >> 1) & 0x01;
+        let low = a | (a << 1) | (b << 2) | (b << 3);
+        (!low << 4) | low
+    }
+
+    #[test]
+    fn access_write_sends_the_data_and_its_complement_then_checks_the_confirm() {
+        let mut wire = MockOneWire::new(&[DS2413]);
+        wire.reads = [vec![WRITE_CONFIRMED, 0xFE], vec![ds2413_status(0x02)]].into();
+        let switch = Switch::from_rom(&DS2413).unwrap();
+        assert_eq!(block_on(switch.write_latches(&mut wire, 0x02)), Ok(()));
+        assert_eq!(wire.writes[0], frame(&DS2413, &[ACCESS_WRITE, 0xFE, 0x01]));    // Unused bits written as 1s
+        assert_eq!(wire.writes[1], frame(&DS2413, &[ACCESS_READ]));
+    }
+
+    #[test]
+    fn access_write_without_confirm_fails() {
+        let mut wire = MockOneWire::new(&[DS2413]);     // Nothing answers, the bus reads as all 1s
+        let switch = Switch::from_rom(&DS2413).unwrap();
+        assert_eq!(block_on(switch.write_latches(&mut wire, 0x02)), Err(SensorError::CrcMismatch));
+    }
+
+    #[test]
+    fn latch_that_did_not_change_is_a_mismatch() {
+        let mut wire = MockOneWire::new(&[DS2413]);
+        wire.reads = [vec![WRITE_CONFIRMED, 0xFE], vec![ds2413_status(0x03)]].into();
+        let switch = Switch::from_rom(&DS2413).unwrap();
+        assert_eq!(block_on(switch.write_latches(&mut wire, 0x02)), Err(SensorError::ConfigMismatch));
+    }
+
+    #[test]
+    fn ds2413_latches_are_decoded_from_the_status() {
+        let switch = Switch::from_rom(&DS2413).unwrap();
+        for latches in 0..4 {
+            let mut wire = MockOneWire::new(&[DS2413]);
+            wire.reads = [vec![ds2413_status(latches)]].into();
+            assert_eq!(block_on(switch.read_latches(&mut wire)), Ok(latches));
+        }
+    }
+
+    #[test]
+    fn ds2413_status_with_a_bad_complement_is_rejected() {
+        let mut wire = MockOneWire::new(&[DS2413]);
+        wire.reads = [vec![ds2413_status(0x01) ^ 0x10]].into();
+        let switch = Switch::from_rom(&DS2413).unwrap();
+        assert_eq!(block_on(switch.read_latches(&mut wire)), Err(SensorError::CrcMismatch));
+    }
+
+    #[test]
+    fn ds2408_latches_are_read_from_their_register() {
+        let mut wire = MockOneWire::new(&[DS2408]);
+        wire.reads = [vec![0x5A]].into();
+        let switch = Switch::from_rom(&DS2408).unwrap();
+        assert_eq!(block_on(switch.read_latches(&mut wire)), Ok(0x5A));
+        assert_eq!(wire.writes[0], frame(&DS2408, &[READ_REGISTERS, DS2408_LATCH_REGISTER, 0x00]));
+    }
+
+    #[test]
+    fn apply_switches_each_role_on_its_assigned_channel() {
+        const ASSIGNMENTS: &[RoleAssignment] = &[
+            RoleAssignment { rom: None, channel: 0, role: OutputRole::Heater },      // First switch found
+            RoleAssignment { rom: None, channel: 1, role: OutputRole::Cooler },
+            RoleAssignment { rom: Some(DS2408), channel: 3, role: OutputRole::Fan },
+            RoleAssignment { rom: Some(DS2408), channel: 9, role: OutputRole::Valve },   // No such channel
+        ];
+        let unassigned = [0x3A, 0x03, 0, 0, 0, 0, 0, 0];
+        let mut wire = MockOneWire::new(&[DS2413, DS2408, unassigned]);
+        wire.reads = [vec![WRITE_CONFIRMED, 0xFE], vec![ds2413_status(0x02)], vec![WRITE_CONFIRMED, 0xF7], vec![0xF7]].into();
+        let mut actuators = Actuators::new(ASSIGNMENTS);
+        actuators.discover(&[DS2413, [0x28, 0, 0, 0, 0, 0, 0, 0], DS2408, unassigned]);
+        assert_eq!(actuators.switches().len(), 3);
+        let states = RoleStates { heater: true, fan: true, valve: true, ..RoleStates::new() };
+        assert_eq!(block_on(actuators.apply(&mut wire, states)), Ok(()));
+        assert_eq!(wire.writes.len(), 4);   // A write and a read back for each assigned switch only
+        assert_eq!(wire.writes[0], frame(&DS2413, &[ACCESS_WRITE, 0xFE, 0x01]));
+        assert_eq!(wire.writes[2], frame(&DS2408, &[ACCESS_WRITE, 0xF7, 0x08]));
+    }
+}
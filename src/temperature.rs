@@ -0,0 +1,89 @@
+#[allow(unused_imports)]   // The host tests link std, which has these maths functions built in
+use micromath::F32Ext;
+
+use crate::sensor::SensorError;
+
+/// A probe that the control loop can take temperature readings from
+#[allow(async_fn_in_trait)]
+pub trait TemperatureSensor {
+    /// Take a new reading in degrees C, waiting for any conversion the sensor needs
+    async fn read_temperature(&mut self) -> Result<f32, SensorError>;
+}
+
+/// Unit temperatures are shown, logged and set in. Temperatures are always held in degrees C internally.
+#[derive(Copy, Clone, PartialEq, Debug, defmt::Format)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Every unit, in the order they are offered in the menu and numbered in the saved settings
+    pub const ALL: [TemperatureUnit; 2] = [TemperatureUnit::Celsius, TemperatureUnit::Fahrenheit];
+
+    /// Position in `ALL`
+    pub const fn index(&self) -> usize {
+        match self {
+            TemperatureUnit::Celsius => 0,
+            TemperatureUnit::Fahrenheit => 1,
+        }
+    }
+
+    pub const fn to_celsius(&self, value: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub const fn from_celsius(&self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub const fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "C",
+            TemperatureUnit::Fahrenheit => "F",
+        }
+    }
+
+    /// How far one key press moves the setpoint, in this unit
+    pub const fn step(&self) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => 0.5,
+            TemperatureUnit::Fahrenheit => 1.0,
+        }
+    }
+
+    /// Round a value in this unit to the nearest setpoint step
+    pub fn round_to_step(&self, value: f32) -> f32 {
+        (value / self.step()).round() * self.step()
+    }
+
+    /// Limits given in degrees C converted to this unit, rounded inwards to whole setpoint steps
+    pub fn limits(&self, min: f32, max: f32) -> (f32, f32) {
+        let step = self.step();
+        ((self.from_celsius(min) / step).ceil() * step, (self.from_celsius(max) / step).floor() * step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_rounded_inwards_to_whole_steps() {
+        assert_eq!(TemperatureUnit::Celsius.limits(11.0, 27.0), (11.0, 27.0));
+        assert_eq!(TemperatureUnit::Fahrenheit.limits(11.0, 27.0), (52.0, 80.0));
+    }
+
+    #[test]
+    fn index_matches_the_order_of_all() {
+        for (index, unit) in TemperatureUnit::ALL.iter().enumerate() {
+            assert_eq!(unit.index(), index);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::sensor::SensorError;
+use crate::temperature::TemperatureSensor;
+
+/// Thermocouple to digital converters supported
+#[derive(Copy, Clone, PartialEq)]
+pub enum ThermocoupleType {
+    Max31855,   // 14-bit, -270 to 1800 degrees C, detects open and shorts to GND or VCC
+    Max6675,    // 12-bit, 0 to 1024 degrees C, only detects an open thermocouple
+}
+
+/// K-type thermocouple read through a MAX31855 or MAX6675 on SPI (mode 0, up to 4.3MHz)
+pub struct Thermocouple<SPI> {
+    spi: SPI,
+    kind: ThermocoupleType,
+}
+
+impl<SPI> Thermocouple<SPI>
+where
+    SPI: SpiDevice,
+{
+    pub fn new(spi: SPI, kind: ThermocoupleType) -> Self {
+        Self { spi, kind }
+    }
+
+    /// Hot junction temperature in degrees C
+    pub async fn temperature(&mut self) -> Result<f32, SensorError> {
+        match self.kind {
+            ThermocoupleType::Max31855 => {
+                let mut data = [0u8; 4];
+                self.spi.read(&mut data).await.map_err(|_| SensorError::BusError)?;
+                Self::decode_max31855(u32::from_be_bytes(data))
+            }
+            ThermocoupleType::Max6675 => {
+                let mut data = [0u8; 2];
+                self.spi.read(&mut data).await.map_err(|_| SensorError::BusError)?;
+                Self::decode_max6675(u16::from_be_bytes(data))
+            }
+        }
+    }
+
+    /// Bits 31 - 18 are the signed temperature in 0.25 degree steps, bit 16 flags a fault and bits 2 - 0 say which one
+    fn decode_max31855(frame: u32) -> Result<f32, SensorError> {
+        if frame == 0xFFFF_FFFF {
+            return Err(SensorError::NoPresence);   // MISO floating high, nothing fitted
+        }
+        if frame & 0x0001_0000 != 0 {
+            return Err(match frame & 0x07 {
+                fault if fault & 0x01 != 0 => SensorError::OpenCircuit,
+                fault if fault & 0x02 != 0 => SensorError::ShortToGround,
+                _ => SensorError::ShortToVcc,
+            });
+        }
+        let raw = (frame as i32) >> 18;    // Arithmetic shift keeps the sign
+        Ok(raw as f32 * 0.25)
+    }
+
+    /// Bits 14 - 3 are the temperature in 0.25 degree steps and bit 2 is set when the thermocouple is open
+    fn decode_max6675(frame: u16) -> Result<f32, SensorError> {
+        if frame == 0xFFFF {
+            return Err(SensorError::NoPresence);   // Bit 15 always reads 0 from a real device
+        }
+        if frame & 0x0004 != 0 {
+            return Err(SensorError::OpenCircuit);
+        }
+        Ok((frame >> 3) as f32 * 0.25)
+    }
+}
+
+impl<SPI> TemperatureSensor for Thermocouple<SPI>
+where
+    SPI: SpiDevice,
+{
+    async fn read_temperature(&mut self) -> Result<f32, SensorError> {
+        self.temperature().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSpi;
+    use embassy_futures::block_on;
+
+    fn max31855(frame: u32) -> Result<f32, SensorError> {
+        let mut thermocouple = Thermocouple::new(MockSpi::new(&[&frame.to_be_bytes()]), ThermocoupleType::Max31855);
+        block_on(thermocouple.temperature())
+    }
+
+    fn max6675(frame: u16) -> Result<f32, SensorError> {
+        let mut thermocouple = Thermocouple::new(MockSpi::new(&[&frame.to_be_bytes()]), ThermocoupleType::Max6675);
+        block_on(thermocouple.temperature())
+    }
+
+    #[test]
+    fn max31855_temperatures_match_the_datasheet() {
+        assert_eq!(max31855(0x6400_0000), Ok(1600.0));
+        assert_eq!(max31855(0x0004_0000), Ok(0.25));
+        assert_eq!(max31855(0x0000_0000), Ok(0.0));
+        assert_eq!(max31855(0xF060_0000), Ok(-250.0));     // Negative values keep their sign
+        assert_eq!(max31855(0xFFFC_0000), Ok(-0.25));
+    }
+
+    #[test]
+    fn max31855_fault_bits_are_reported() {
+        assert_eq!(max31855(0x0001_0001), Err(SensorError::OpenCircuit));
+        assert_eq!(max31855(0x0001_0002), Err(SensorError::ShortToGround));
+        assert_eq!(max31855(0x0001_0004), Err(SensorError::ShortToVcc));
+        assert_eq!(max31855(0x0640_0002), Ok(100.0));    // Fault bits without the fault flag are ignored
+    }
+
+    #[test]
+    fn max6675_temperature_and_open_thermocouple() {
+        assert_eq!(max6675(0x0C80), Ok(100.0));
+        assert_eq!(max6675(0x0008), Ok(0.25));
+        assert_eq!(max6675(0x0C84), Err(SensorError::OpenCircuit));
+    }
+
+    #[test]
+    fn all_ones_frame_is_no_presence() {
+        assert_eq!(max31855(0xFFFF_FFFF), Err(SensorError::NoPresence));
+        assert_eq!(max6675(0xFFFF), Err(SensorError::NoPresence));
+    }
+
+    #[test]
+    fn failed_spi_read_is_a_bus_error() {
+        let mut thermocouple = Thermocouple::new(MockSpi::default(), ThermocoupleType::Max31855);
+        assert_eq!(block_on(thermocouple.temperature()), Err(SensorError::BusError));
+    }
+}